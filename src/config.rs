@@ -1,159 +1,636 @@
-use std::{collections::HashMap, fs::File, io::Read, path::PathBuf};
+use std::{collections::{HashMap, HashSet}, fs::File, io::Read, path::{Path, PathBuf}, sync::{atomic::{AtomicBool, Ordering}, OnceLock}, time::Duration};
 
 use caps_shortcut::Key;
-use key_from_str::KeyFromStr;
-use serde_json::Value;
+use diagnostic::ConfigDiagnostic;
+use key_from_str::{suggest_key_name, KeyFromStr};
+use model::{BindingModel, CapsState, FeatureModel, StringList};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
 use yaml_rust2::{Yaml, YamlLoader};
 
-use crate::{feature::{InputKey, InputKeyAction, InputTextAction, MultifunctionalAction}, i18n::{self, text_as_language, Language}, units::{file_io, string::TrimCharMatches}};
+use crate::{feature::{filter::FilterAction, format::FormatAction, transform::{self, Transform, TransformAction}, CapsToggleAction, CommandLine, InputKey, InputKeyAction, InputTextAction, MultifunctionalAction, RunAction}, i18n::{self, text_as_language, Language}, units::{file_io, keyboard, string::did_you_mean}};
 
 static CONFIG_DIR: &str = "config";
+static CONFIG_DIR_ENV: &str = "CAPSLOCK_FORGE_CONFIG";
+static USER_CONFIG_DIR_NAME: &str = "capslock-forge";
 static CAPSLOCK_FORGET_CONFIG_FILE_NAME: &str = "capslock_forget_config.yaml";
 static REPLACE_TEXT_FIEL_NAME: &str = "replace_text.ini";
+static CONF_D_DIR: &str = "conf.d";
 static DEFAULT_CAPSLOCK_FORGET_CONFIG_BYTE: &[u8] = include_bytes!("../default_config/capslock_forget_config.yaml");
 static DEFAULT_REPLACE_TEXT_BYTE: &[u8] = include_bytes!("../default_config/replace_text.ini");
 
 mod key_from_str;
 
+pub mod diagnostic;
+
+pub mod model;
+
+pub mod schema;
+
+/// 单个修饰键的要求
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Modifier {
+    /// 不按下
+    Off,
+    /// 按下左右任意一侧
+    Either,
+    Left,
+    Right,
+    /// 不限，按下与否都能触发，模拟输入时保留实际按下的状态
+    Any,
+}
+
+impl Modifier {
+    /// `pressed`为该修饰键是否按下，左右侧另外读取物理按键状态
+    fn matches(&self, pressed: bool, left: Key, right: Key) -> bool {
+        match self {
+            Modifier::Off => !pressed,
+            Modifier::Either => pressed,
+            Modifier::Left => pressed && keyboard::is_key_down(left),
+            Modifier::Right => pressed && keyboard::is_key_down(right),
+            Modifier::Any => true,
+        }
+    }
+
+    /// 能满足`other`的按键状态是否也一定能满足`self`
+    fn covers(&self, other: &Self) -> bool {
+        match self {
+            Modifier::Any => true,
+            Modifier::Either => matches!(other, Modifier::Either | Modifier::Left | Modifier::Right),
+            _ => self == other,
+        }
+    }
+
+    /// 模拟输入时按下的按键，左右任意时按下左侧
+    pub fn key(&self, left: Key, right: Key) -> Option<Key> {
+        match self {
+            Modifier::Off | Modifier::Any => None,
+            Modifier::Either | Modifier::Left => Some(left),
+            Modifier::Right => Some(right),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ModifierKey {
-    pub ctrl: bool,
-    pub shift: bool,
-    pub alt: bool,
-    pub meta: bool,
+    pub ctrl: Modifier,
+    pub shift: Modifier,
+    pub alt: Modifier,
+    pub meta: Modifier,
 }
 
 impl ModifierKey {
     pub fn match_key(&self, ctrl: bool, shift: bool, alt: bool, meta: bool) -> bool {
-        self.ctrl == ctrl && self.shift == shift && self.alt == alt && self.meta == meta
+        self.ctrl.matches(ctrl, Key::ControlLeft, Key::ControlRight)
+            && self.shift.matches(shift, Key::ShiftLeft, Key::ShiftRight)
+            && self.alt.matches(alt, Key::Alt, Key::AltGr)
+            && self.meta.matches(meta, Key::MetaLeft, Key::MetaRight)
     }
 
     pub fn new() -> Self {
         Self { 
-            ctrl: false,
-            shift: false,
-            alt: false,
-            meta: false
+            ctrl: Modifier::Off,
+            shift: Modifier::Off,
+            alt: Modifier::Off,
+            meta: Modifier::Off,
+        }
+    }
+
+    /// 监听器报告的修饰键状态，不区分左右
+    pub fn pressed(ctrl: bool, shift: bool, alt: bool, meta: bool) -> Self {
+        let modifier = |pressed: bool| if pressed { Modifier::Either } else { Modifier::Off };
+        Self {
+            ctrl: modifier(ctrl),
+            shift: modifier(shift),
+            alt: modifier(alt),
+            meta: modifier(meta),
+        }
+    }
+
+    /// 能触发`other`的修饰键组合是否也一定能触发`self`
+    pub fn covers(&self, other: &Self) -> bool {
+        self.ctrl.covers(&other.ctrl)
+            && self.shift.covers(&other.shift)
+            && self.alt.covers(&other.alt)
+            && self.meta.covers(&other.meta)
+    }
+
+    /// 模拟输入时需要按下的修饰键，按Ctrl、Shift、Alt、Meta的顺序
+    pub fn keys(&self) -> Vec<Key> {
+        [
+            self.ctrl.key(Key::ControlLeft, Key::ControlRight),
+            self.shift.key(Key::ShiftLeft, Key::ShiftRight),
+            self.alt.key(Key::Alt, Key::AltGr),
+            self.meta.key(Key::MetaLeft, Key::MetaRight),
+        ].into_iter().flatten().collect()
+    }
+
+    /// 按修饰键名（小写）设置修饰键，不是修饰键名时返回`false`
+    /// 
+    /// `*Shift`表示不限Shift，单独的`*`表示不限全部修饰键
+    fn set(&mut self, name: &str) -> bool {
+        if name == "*" {
+            *self = Self { ctrl: Modifier::Any, shift: Modifier::Any, alt: Modifier::Any, meta: Modifier::Any };
+            return true
+        }
+
+        let (name, wildcard) = match name.strip_prefix('*') {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+
+        match MODIFIER_TABLE.iter().find(|(modifier_name, _, _)| *modifier_name == name) {
+            Some((_, slot, modifier)) => {
+                *self.slot_mut(*slot) = match (wildcard, modifier) {
+                    (false, modifier) => *modifier,
+                    (true, Modifier::Either) => Modifier::Any,
+                    (true, _) => return false,
+                };
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// 按键本身隐含的修饰键，只补充未指定的修饰键
+    fn imply(&mut self, implied: &ModifierKey) {
+        let mut implied = *implied;
+        for slot in [ModifierSlot::Ctrl, ModifierSlot::Shift, ModifierSlot::Alt, ModifierSlot::Meta] {
+            let modifier = self.slot_mut(slot);
+            if *modifier == Modifier::Off {
+                *modifier = *implied.slot_mut(slot);
+            }
+        }
+    }
+
+    fn slot_mut(&mut self, slot: ModifierSlot) -> &mut Modifier {
+        match slot {
+            ModifierSlot::Ctrl => &mut self.ctrl,
+            ModifierSlot::Shift => &mut self.shift,
+            ModifierSlot::Alt => &mut self.alt,
+            ModifierSlot::Meta => &mut self.meta,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ModifierSlot {
+    Ctrl,
+    Shift,
+    Alt,
+    Meta,
+}
+
+/// 修饰键名（小写）与对应的修饰键
+static MODIFIER_TABLE: &[(&str, ModifierSlot, Modifier)] = &[
+    ("ctrl", ModifierSlot::Ctrl, Modifier::Either),
+    ("lctrl", ModifierSlot::Ctrl, Modifier::Left),
+    ("leftctrl", ModifierSlot::Ctrl, Modifier::Left),
+    ("rctrl", ModifierSlot::Ctrl, Modifier::Right),
+    ("rightctrl", ModifierSlot::Ctrl, Modifier::Right),
+    ("shift", ModifierSlot::Shift, Modifier::Either),
+    ("lshift", ModifierSlot::Shift, Modifier::Left),
+    ("leftshift", ModifierSlot::Shift, Modifier::Left),
+    ("rshift", ModifierSlot::Shift, Modifier::Right),
+    ("rightshift", ModifierSlot::Shift, Modifier::Right),
+    ("alt", ModifierSlot::Alt, Modifier::Either),
+    ("lalt", ModifierSlot::Alt, Modifier::Left),
+    ("leftalt", ModifierSlot::Alt, Modifier::Left),
+    ("ralt", ModifierSlot::Alt, Modifier::Right),
+    ("rightalt", ModifierSlot::Alt, Modifier::Right),
+    ("meta", ModifierSlot::Meta, Modifier::Either),
+    ("lmeta", ModifierSlot::Meta, Modifier::Left),
+    ("leftmeta", ModifierSlot::Meta, Modifier::Left),
+    ("lwin", ModifierSlot::Meta, Modifier::Left),
+    ("rmeta", ModifierSlot::Meta, Modifier::Right),
+    ("rightmeta", ModifierSlot::Meta, Modifier::Right),
+    ("rwin", ModifierSlot::Meta, Modifier::Right),
+];
+
+/// 全部修饰键名（小写），包括`*`开头的不限修饰键
+pub fn modifier_names() -> impl Iterator<Item = String> {
+    let wildcard_list = MODIFIER_TABLE.iter()
+        .filter(|(_, _, modifier)| *modifier == Modifier::Either)
+        .map(|(name, _, _)| format!("*{name}"));
+
+    MODIFIER_TABLE.iter()
+        .map(|(name, _, _)| name.to_string())
+        .chain(wildcard_list)
+        .chain(std::iter::once("*".to_string()))
+}
+
+impl std::fmt::Display for ModifierKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = [(self.ctrl, "Ctrl"), (self.shift, "Shift"), (self.alt, "Alt"), (self.meta, "Meta")];
+        for (modifier, name) in list.iter() {
+            match modifier {
+                Modifier::Off => (),
+                Modifier::Either => write!(f, "{} + ", name)?,
+                Modifier::Left => write!(f, "L{} + ", name)?,
+                Modifier::Right => write!(f, "R{} + ", name)?,
+                Modifier::Any => write!(f, "*{} + ", name)?,
+            }
         }
+        Ok(())
     }
 }
 
+/// 快捷键来自哪个配置文件的第几项
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindingSource {
+    pub file: PathBuf,
+    /// 在`capslock_shortcut`中的序号，从0开始
+    pub index: usize,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl std::fmt::Display for BindingSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "binding #{} in {}", self.index + 1, self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// 序列快捷键中的一步
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SequenceStep {
+    pub key: Key,
+    pub modifier_key: ModifierKey,
+    /// 是否按住`Capslock`按下，为`false`时在松开`Capslock`后按下（前导键序列）
+    pub hold_caps: bool,
+}
+
 #[derive(Debug)]
 pub struct ShortcutKeyConfig {
     pub key: Key,
     pub modifier_key: ModifierKey,
+    /// 序列快捷键在第一个按键之后的按键，单个按键的快捷键为空
+    pub sequence: Vec<SequenceStep>,
+    /// 只在大写锁定开启（`true`）或关闭（`false`）时触发，`None`为不限
+    pub caps_state: Option<bool>,
+    /// 连按次数，单次按下为1
+    pub taps: u32,
     pub feature: CapslockForgetFeature,
+    pub source: BindingSource,
 }
 
 impl ShortcutKeyConfig {
-    fn from_entry(entry: &Value) -> Result<Self, ShortcutKeyConfigFileFormatError> {
-        let (key, modifier_key) = match entry["key"].as_str() {
-            Some(s) => {
-                parse_shortcut_key_text(s)?
+    /// 触发按键与修饰键是否完全相同
+    pub fn same_trigger(&self, other: &Self) -> bool {
+        self.key == other.key && self.modifier_key == other.modifier_key && self.sequence == other.sequence && self.caps_state == other.caps_state
+            && self.taps == other.taps
+    }
+
+    /// 能触发`other`的按键是否也一定能触发`self`，即`self`排在前面时`other`永远不会触发
+    /// 
+    /// 序列快捷键之间，一个是另一个的开头时同样视为遮蔽。
+    /// 连按次数不同的快捷键可以共存，但与以该按键开头的序列快捷键不能共存
+    pub fn shadows(&self, other: &Self) -> bool {
+        let len = self.sequence.len().min(other.sequence.len());
+        self.key == other.key && self.modifier_key.covers(&other.modifier_key) && self.sequence[..len] == other.sequence[..len]
+            && (self.caps_state.is_none() || self.caps_state == other.caps_state)
+            && (self.taps == other.taps || !self.sequence.is_empty() || !other.sequence.is_empty())
+    }
+
+    /// 当前大写锁定状态是否满足`caps_state`
+    pub fn caps_state_matches(&self) -> bool {
+        self.caps_state.is_none_or(|on| on == keyboard::is_caps_lock_on())
+    }
+
+    pub fn trigger_text(&self) -> String {
+        let first = SequenceStep { key: self.key, modifier_key: self.modifier_key, hold_caps: false };
+        std::iter::once(&first)
+            .chain(self.sequence.iter())
+            .map(|step| {
+                let caps = if step.hold_caps { "Caps + " } else { "" };
+                format!("{}{}{}", caps, step.modifier_key, key_from_str::key_name(step.key))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+            + &match self.taps {
+                1 => String::new(),
+                taps => format!(" x{taps}"),
+            }
+            + match self.caps_state {
+                Some(true) => " (caps lock on)",
+                Some(false) => " (caps lock off)",
+                None => "",
+            }
+    }
+
+    /// 解析单个快捷键
+    /// 
+    /// 未使用的字段按`unknown_field`处理，为警告时快捷键照常加载，警告放入`warnings`
+    fn from_entry(entry: &Value, source: BindingSource, unknown_field: ReportLevel, warnings: &mut Vec<ShortcutKeyConfigFileFormatError>) -> Result<Self, ShortcutKeyConfigFileFormatError> {
+        let Some(object) = entry.as_object() else {
+            return Err(ShortcutKeyConfigFileFormatError::JsonError(Box::new(
+                ConfigDiagnostic::new("binding must be a mapping with 'key' and 'feature'")
+            )))
+        };
+
+        if !(entry["key"].is_string() || entry["key"].is_array()) {
+            return Err(ShortcutKeyConfigFileFormatError::JsonError(Box::new(
+                ConfigDiagnostic::new("missing field 'key', or 'key' is not a string or a list")
+            )));
+        }
+
+        let feature = match entry["feature"].as_str() {
+            Some(feature) => feature,
+            None => return Err(ShortcutKeyConfigFileFormatError::JsonError(Box::new(
+                ConfigDiagnostic::new("missing or non-string field 'feature'")
+            ))),
+        };
+
+        if model::feature_fields(feature).is_none() {
+            let suggestion = did_you_mean(feature, model::feature_names()).map(|s| s.to_string());
+            let diagnostic = ConfigDiagnostic::new(format!("unknown feature '{feature}'"))
+                .with_suggestion(suggestion)
+                .with_field("feature");
+            return Err(ShortcutKeyConfigFileFormatError::FeatureError(Box::new(diagnostic)))
+        }
+
+        if unknown_field != ReportLevel::Ignore {
+            for (field, suggestion) in model::unknown_fields(object, feature) {
+                let diagnostic = ConfigDiagnostic::new(format!("unknown field '{field}' for feature '{feature}'"))
+                    .with_suggestion(suggestion.map(|s| s.to_string()))
+                    .with_field(field);
+                let err = ShortcutKeyConfigFileFormatError::ValueError(Box::new(diagnostic));
+                if unknown_field == ReportLevel::Error {
+                    return Err(err);
+                }
+                warnings.push(err);
             }
-            None => return Err(ShortcutKeyConfigFileFormatError::JsonError(entry.to_string()))
+        }
+
+        // 快捷键本身的字段与功能的字段分开解析，未知的字段已在上面处理
+        let (binding_entry, feature_entry) = object.clone().into_iter()
+            .partition::<Map<String, Value>, _>(|(field, _)| model::binding_fields().contains(&field.as_str()));
+        let feature_entry = known_feature_fields(feature_entry, feature);
+        let binding: BindingModel = parse_model(binding_entry)?;
+
+        let mut sequence = parse_key_sequence(&binding.key.0).map_err(|err| err.with_field("key"))?;
+        let SequenceStep { key, modifier_key, .. } = sequence.remove(0);
+
+        let taps = binding.taps.unwrap_or(1);
+        let taps_error = |explanation: &str| {
+            ShortcutKeyConfigFileFormatError::ValueError(Box::new(ConfigDiagnostic::new(explanation).with_field("taps")))
         };
+        if taps == 0 {
+            return Err(taps_error("'taps' must be at least 1"))
+        }
+        if taps > 1 && !sequence.is_empty() {
+            return Err(taps_error("'taps' can not be used with a key sequence"))
+        }
 
-        let feature = CapslockForgetFeature::from_value(entry)?;
+        let feature = CapslockForgetFeature::from_model(parse_model(feature_entry)?)?;
         
         Ok(Self {
             key,
             modifier_key,
-            feature
+            sequence,
+            caps_state: binding.caps_state.map(|caps_state| caps_state == CapsState::On),
+            taps,
+            feature,
+            source,
         })
     }
 }
 
+/// 按模型解析，类型错误时指出出错的字段
+fn parse_model<T: DeserializeOwned>(entry: Map<String, Value>) -> Result<T, ShortcutKeyConfigFileFormatError> {
+    serde_json::from_value(Value::Object(entry.clone())).map_err(|err| {
+        let diagnostic = match model::failing_field::<T>(&entry) {
+            Some(field) => ConfigDiagnostic::new(format!("'{field}': {err}")).with_field(field),
+            None => ConfigDiagnostic::new(err.to_string()),
+        };
+        ShortcutKeyConfigFileFormatError::ValueError(Box::new(diagnostic))
+    })
+}
+
+/// 只保留`feature`与该功能用到的字段
+fn known_feature_fields(mut entry: Map<String, Value>, feature: &str) -> Map<String, Value> {
+    let fields = model::feature_fields(feature).unwrap_or_default();
+    entry.retain(|field, _| field == model::FEATURE_TAG || fields.contains(&field.as_str()));
+    entry
+}
+
 #[derive(Debug)]
 pub enum CapslockForgetFeature {
     InputText(InputTextAction),
     Input(InputKeyAction),
     Multifunctional(MultifunctionalAction),
+    CapsToggle(CapsToggleAction),
+    Run(RunAction),
+    Filter(FilterAction),
+    Transform(TransformAction),
+    Format(FormatAction),
 }
 
 impl CapslockForgetFeature {
-    fn from_value(value: &Value) -> Result<Self, ShortcutKeyConfigFileFormatError> {
-
-        fn to_string_list(value: &Value) -> Result<Vec<String>, ShortcutKeyConfigFileFormatError> {
-            let mut text_list = vec![];
-            if let Some(array) = value.as_array() {
-                let array = array.iter().map(|v| v.to_string().trim_char_matches("\"").to_string());
-                text_list.extend(array);
-            } if let Some(value) = value.as_str() {
-                text_list.push(value.to_string());
-            }
-            if text_list.is_empty() {
-                return Err(ShortcutKeyConfigFileFormatError::ValueError(value.to_string()))
-            }
-            Ok(text_list)
-        }
+    fn from_model(model: FeatureModel) -> Result<Self, ShortcutKeyConfigFileFormatError> {
+        match model {
+            FeatureModel::InputText { text } => {
+                let text_list = InputTextAction::new(text.0);
+                Ok(Self::InputText(text_list))
+            },
 
-        match value["feature"].as_str() {
-            Some(feature) => {
-                match feature {
-                    "input_text" => {
-                        let text_list = to_string_list(&value["text"])?;
-                        let text_list = InputTextAction::new(text_list);
-                        Ok(Self::InputText(text_list))
-                    }
+            FeatureModel::Input { action, delay } => {
+                let mut list = vec![];
 
-                    "input" => {
-                        let action_list = to_string_list(&value["action"])?;
-                        let delay = value["delay"].as_u64().unwrap_or(0);
+                for action in action.0 {
+                    let mut action_key = InputKey::with_str(&action).map_err(|err| err.with_field("action"))?;
+                    action_key.delay = delay;
 
-                        let mut list = vec![];
+                    list.push(action_key);    
+                }
+                Ok(Self::Input(InputKeyAction::new(list)))
+            },
 
-                        for action in action_list {
-                            let mut action_key = if let Ok(action_key) = InputKey::with_str(&action) { action_key } else {
-                                return Err(ShortcutKeyConfigFileFormatError::ValueError(action.to_string()))
-                            };
-                            action_key.delay = delay;
+            FeatureModel::Multifunctional {} => {
+                Ok(Self::Multifunctional(MultifunctionalAction))
+            },
 
-                            list.push(action_key);    
-                        }
-                        Ok(Self::Input(InputKeyAction::new(list)))
-                    }
+            FeatureModel::CapsToggle {} => {
+                Ok(Self::CapsToggle(CapsToggleAction))
+            },
 
-                    "multifunctional" => {
-                        Ok(Self::Multifunctional(MultifunctionalAction))
-                    }
-                    
-                    _ => return Err(ShortcutKeyConfigFileFormatError::FeatureError(feature.to_string())),
+            FeatureModel::Run { program, args, cwd, env, shell, wait } => {
+                let command_line = command_line(program, args, cwd, env, shell)?;
+                Ok(Self::Run(RunAction::new(command_line, wait)))
+            },
+
+            FeatureModel::Filter { program, args, cwd, env, shell, timeout, max_size, paste } => {
+                let command_line = command_line(program, args, cwd, env, shell)?;
+                let timeout = Duration::from_millis(timeout.unwrap_or(5000));
+                let max_size = max_size.unwrap_or(1024 * 1024);
+                Ok(Self::Filter(FilterAction::new(command_line, timeout, max_size, paste)))
+            },
+
+            FeatureModel::Transform { transform, paste } => {
+                let mut transform_list = vec![];
+                for name in transform.0 {
+                    let Some(item) = Transform::from_name(&name) else {
+                        let suggestion = did_you_mean(&name, transform::transform_names()).map(|s| s.to_string());
+                        let diagnostic = ConfigDiagnostic::new(format!("unknown transform '{name}'"))
+                            .with_suggestion(suggestion)
+                            .with_field("transform");
+                        return Err(ShortcutKeyConfigFileFormatError::ValueError(Box::new(diagnostic)))
+                    };
+                    transform_list.push(item);
                 }
+                Ok(Self::Transform(TransformAction::new(transform_list, paste)))
+            },
+
+            FeatureModel::Format { syntax, style, indent, paste } => {
+                Ok(Self::Format(FormatAction::new(syntax, style, indent.unwrap_or(2), paste)))
             },
-            None => return Err(ShortcutKeyConfigFileFormatError::JsonError(value.to_string())),
         }
     }
 }
 
 #[derive(Debug)]
 pub enum ShortcutKeyConfigFileFormatError {
-    JsonError(String),
-    KeyError(String),
-    ValueError(String),
-    FeatureError(String),
+    JsonError(Box<ConfigDiagnostic>),
+    KeyError(Box<ConfigDiagnostic>),
+    ValueError(Box<ConfigDiagnostic>),
+    FeatureError(Box<ConfigDiagnostic>),
+    /// 重复或被遮蔽而永远不会触发的快捷键
+    ConflictError(Box<ConfigDiagnostic>),
+    /// 无法读取或不是有效YAML的额外配置文件
+    FileError(Box<ConfigDiagnostic>),
+}
+
+impl ShortcutKeyConfigFileFormatError {
+    pub fn diagnostic(&self) -> &ConfigDiagnostic {
+        match self {
+            Self::JsonError(diagnostic)
+            | Self::KeyError(diagnostic)
+            | Self::ValueError(diagnostic)
+            | Self::FeatureError(diagnostic)
+            | Self::ConflictError(diagnostic)
+            | Self::FileError(diagnostic) => diagnostic,
+        }
+    }
+
+    fn diagnostic_mut(&mut self) -> &mut ConfigDiagnostic {
+        match self {
+            Self::JsonError(diagnostic)
+            | Self::KeyError(diagnostic)
+            | Self::ValueError(diagnostic)
+            | Self::FeatureError(diagnostic)
+            | Self::ConflictError(diagnostic)
+            | Self::FileError(diagnostic) => diagnostic,
+        }
+    }
+
+    /// 无法读取的配置文件
+    pub fn io_error(path: &Path, err: &std::io::Error) -> Self {
+        let mut diagnostic = ConfigDiagnostic::new(err.to_string());
+        diagnostic.file = Some(path.to_path_buf());
+        Self::FileError(Box::new(diagnostic))
+    }
+
+    /// 不是有效YAML的配置文件
+    pub fn scan_error(path: &Path, err: &yaml_rust2::ScanError) -> Self {
+        let mut diagnostic = ConfigDiagnostic::new(format!("invalid YAML: {}", err.info()));
+        diagnostic.file = Some(path.to_path_buf());
+        diagnostic.line = Some(err.marker().line());
+        diagnostic.column = Some(err.marker().col() + 1);
+        Self::FileError(Box::new(diagnostic))
+    }
+
+    /// 指定出错的字段，已指定时不覆盖
+    fn with_field<S: Into<String>>(mut self, field: S) -> Self {
+        let diagnostic = self.diagnostic_mut();
+        if diagnostic.field.is_none() {
+            diagnostic.field = Some(field.into());
+        }
+        self
+    }
 }
 
 impl std::fmt::Display for ShortcutKeyConfigFileFormatError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", "config file illegal")
+        write!(f, "{}", self.diagnostic())
     }
 }
 
 
 
+static RESOLVED_CONFIG_DIR: OnceLock<(PathBuf, ConfigDirSource)> = OnceLock::new();
+
+/// 配置文件夹的来源
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigDirSource {
+    /// 命令行参数`--config-dir`
+    CommandLine,
+    /// 环境变量`CAPSLOCK_FORGE_CONFIG`
+    Environment,
+    /// 当前用户的配置文件夹
+    User,
+    /// 便携模式，程序所在目录下的`config`，只在系统没有用户配置文件夹时使用
+    Portable,
+}
+
+/// 确定配置文件夹，只在启动时生效一次
+/// 
+/// 顺序：命令行参数 → 环境变量 → 用户配置文件夹 → 程序旁的`config`（系统没有用户配置文件夹时）
+pub fn init_config_dir(command_line_dir: Option<PathBuf>) {
+    RESOLVED_CONFIG_DIR.get_or_init(|| resolve_config_dir(command_line_dir));
+}
+
+fn resolve_config_dir(command_line_dir: Option<PathBuf>) -> (PathBuf, ConfigDirSource) {
+    if let Some(dir) = command_line_dir {
+        return (dir, ConfigDirSource::CommandLine);
+    }
+
+    if let Some(dir) = std::env::var_os(CONFIG_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        return (PathBuf::from(dir), ConfigDirSource::Environment);
+    }
+
+    if let Some(user_dir) = user_config_dir() {
+        return (user_dir, ConfigDirSource::User);
+    }
+
+    let exe_dir = std::env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| PathBuf::from("."));
+    (exe_dir.join(CONFIG_DIR), ConfigDirSource::Portable)
+}
+
+/// 当前用户的配置文件夹，例如Windows下的`%APPDATA%\capslock-forge`
+fn user_config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.filter(|base| !base.as_os_str().is_empty()).map(|base| base.join(USER_CONFIG_DIR_NAME))
+}
+
+fn resolved_config_dir() -> &'static (PathBuf, ConfigDirSource) {
+    RESOLVED_CONFIG_DIR.get_or_init(|| resolve_config_dir(None))
+}
+
+pub fn config_dir() -> PathBuf {
+    resolved_config_dir().0.clone()
+}
+
+pub fn config_dir_source() -> ConfigDirSource {
+    resolved_config_dir().1
+}
+
 pub fn capslock_forget_config_file_path() -> PathBuf {
-    let current_exe = std::env::current_exe().unwrap();
-    let current_dir = current_exe.parent().unwrap();
-    let config_dir = current_dir.join(CONFIG_DIR);
-    config_dir.join(CAPSLOCK_FORGET_CONFIG_FILE_NAME)
+    config_dir().join(CAPSLOCK_FORGET_CONFIG_FILE_NAME)
 }
 
 pub fn replace_text_file_path() -> PathBuf {
-    let current_exe = std::env::current_exe().unwrap();
-    let current_dir = current_exe.parent().unwrap();
-    let config_dir = current_dir.join(CONFIG_DIR);
-    config_dir.join(REPLACE_TEXT_FIEL_NAME)
+    config_dir().join(REPLACE_TEXT_FIEL_NAME)
 }
 
 pub fn init() {
@@ -171,11 +648,20 @@ pub fn init() {
     }
 }
 
-fn parse_yaml_file(path: PathBuf) -> Result<Result<Option<Vec<Yaml>>, yaml_rust2::scanner::ScanError>, std::io::Error> {
+fn read_text_file(path: &PathBuf) -> Result<String, std::io::Error> {
     let mut text = String::new();
     let mut file = File::open(path)?;
     file.read_to_string(&mut text)?;
-    let yaml_item_list = match YamlLoader::load_from_str(&*text) {
+    Ok(text)
+}
+
+fn parse_yaml_file(path: PathBuf) -> Result<Result<Option<Vec<Yaml>>, yaml_rust2::scanner::ScanError>, std::io::Error> {
+    let text = read_text_file(&path)?;
+    parse_yaml_text(&text)
+}
+
+fn parse_yaml_text(text: &str) -> Result<Result<Option<Vec<Yaml>>, yaml_rust2::scanner::ScanError>, std::io::Error> {
+    let yaml_item_list = match YamlLoader::load_from_str(text) {
         Ok(yaml_item_list) => yaml_item_list,
         Err(err) => return Ok(Err(err)),
     };
@@ -210,6 +696,168 @@ pub fn language_or_auto() -> Language {
     }
 }
 
+/// 配置文件的顶层节点，读取或解析失败时返回`Yaml::BadValue`
+fn top_level_config(text: &str) -> Yaml {
+    let yaml_list = parse_yaml_text(text).unwrap_or(Ok(None)).unwrap_or(None).unwrap_or_default();
+    yaml_list.into_iter().next().unwrap_or(Yaml::BadValue)
+}
+
+/// 主配置文件中的顶层设置
+/// 
+/// 每次加载时只读取、解析一次主配置文件，加载过程中的各个步骤都使用同一份设置，
+/// 加载途中保存文件也不会读到前后不一致的值。读取或解析失败时各项为默认值
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// 主配置文件的原文，加载其中的快捷键时直接使用
+    text: Option<String>,
+    /// 严格加载：任一快捷键有误时不启用任何快捷键，默认关闭
+    pub strict_load: bool,
+    /// 快捷键中未使用的字段的处理方式，默认为警告
+    pub unknown_field: ReportLevel,
+    /// 重复或被遮蔽的快捷键的处理方式，默认为警告
+    pub duplicate_binding: ReportLevel,
+    /// 序列快捷键两步之间的最长间隔，默认1000毫秒
+    pub sequence_timeout: u64,
+    /// 序列快捷键未完成时是否重放已按下的按键，默认关闭
+    pub sequence_replay: bool,
+    /// 连按快捷键两次按下之间的最长间隔，默认300毫秒
+    pub tap_interval: u64,
+    /// 单独轻按`Capslock`时的功能，未设置时为`Yaml::BadValue`或`Yaml::Null`
+    caps_tap: Yaml,
+    /// 按住`Capslock`超过该时长后松开不算轻按，默认200毫秒
+    pub caps_tapping_term: u64,
+    /// 配置文件变化时自动重新加载，默认开启
+    pub auto_reload: bool,
+    /// 单个字符的按键名按当前键盘布局解析，默认关闭
+    pub layout_aware_keys: bool,
+    /// `include`列出的文件，相对路径以配置文件夹为准
+    pub include: Vec<PathBuf>,
+}
+
+impl Settings {
+    /// 读取主配置文件的顶层设置
+    pub fn load() -> Self {
+        let text = read_text_file(&capslock_forget_config_file_path()).ok();
+        let yaml = text.as_deref().map(top_level_config).unwrap_or(Yaml::BadValue);
+        let milliseconds = |name: &str, default: u64| yaml[name].as_i64().and_then(|ms| u64::try_from(ms).ok()).unwrap_or(default);
+
+        let config_dir = config_dir();
+        let include = match &yaml["include"] {
+            Yaml::String(include) => vec![config_dir.join(include)],
+            Yaml::Array(include_list) => include_list.iter().filter_map(|include| include.as_str()).map(|include| config_dir.join(include)).collect(),
+            _ => vec![],
+        };
+
+        Self {
+            strict_load: yaml["strict_load"].as_bool().unwrap_or(false),
+            unknown_field: report_level(&yaml["unknown_field"], ReportLevel::Warning),
+            duplicate_binding: report_level(&yaml["duplicate_binding"], ReportLevel::Warning),
+            sequence_timeout: milliseconds("sequence_timeout", 1000),
+            sequence_replay: yaml["sequence_replay"].as_bool().unwrap_or(false),
+            tap_interval: milliseconds("tap_interval", 300),
+            caps_tap: yaml["caps_tap"].clone(),
+            caps_tapping_term: milliseconds("caps_tapping_term", 200),
+            auto_reload: yaml["auto_reload"].as_bool().unwrap_or(true),
+            layout_aware_keys: yaml["layout_aware_keys"].as_bool().unwrap_or(false),
+            include,
+            text,
+        }
+    }
+
+    /// 单独轻按`Capslock`时的功能，写法与快捷键相同但没有`key`，未设置时返回`None`
+    pub fn caps_tap(&self) -> Option<Result<CapslockForgetFeature, ShortcutKeyConfigFileFormatError>> {
+        if self.caps_tap.is_badvalue() || self.caps_tap.is_null() {
+            return None
+        }
+
+        let entry = yaml_to_json(&self.caps_tap);
+        let caps_tap_error = |explanation: String| {
+            let mut diagnostic = ConfigDiagnostic::new(format!("caps_tap: {explanation}"));
+            diagnostic.file = Some(capslock_forget_config_file_path());
+            ShortcutKeyConfigFileFormatError::ValueError(Box::new(diagnostic))
+        };
+
+        if !cfg!(feature = "caps-tap") {
+            return Some(Err(caps_tap_error("not supported by this build, which lacks the 'caps-tap' feature".to_string())))
+        }
+
+        // `caps_tap`中用不到的字段不提示
+        let feature = match entry.as_object() {
+            Some(object) => {
+                let feature = object.get(model::FEATURE_TAG).and_then(|feature| feature.as_str()).unwrap_or_default();
+                parse_model::<FeatureModel>(known_feature_fields(object.clone(), feature))
+                    .and_then(CapslockForgetFeature::from_model)
+                    .map_err(|err| caps_tap_error(err.diagnostic().explanation.clone()))
+            },
+            None => Err(caps_tap_error("expected a mapping with 'feature'".to_string())),
+        };
+        Some(feature)
+    }
+}
+
+/// 配置问题的处理方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportLevel {
+    Ignore,
+    Warning,
+    Error,
+}
+
+/// 读取顶层的`ignore`、`warning`、`error`设置
+fn report_level(yaml: &Yaml, default: ReportLevel) -> ReportLevel {
+    match yaml.as_str() {
+        Some("ignore") => ReportLevel::Ignore,
+        Some("warning") => ReportLevel::Warning,
+        Some("error") => ReportLevel::Error,
+        _ => default,
+    }
+}
+
+/// `run`与`filter`要执行的程序
+fn command_line(program: String, args: Option<StringList>, cwd: Option<String>, env: HashMap<String, String>, shell: bool) -> Result<CommandLine, ShortcutKeyConfigFileFormatError> {
+    if program.trim().is_empty() {
+        return Err(ShortcutKeyConfigFileFormatError::ValueError(Box::new(
+            ConfigDiagnostic::new("'program' must not be empty").with_field("program")
+        )))
+    }
+
+    let args = args.map(|args| args.0).unwrap_or_default()
+        .iter()
+        .map(|arg| expand_path_placeholder(arg))
+        .collect();
+    // 相对路径以配置文件夹为准
+    let cwd = cwd.map(|cwd| config_dir().join(expand_path_placeholder(&cwd)));
+    let env = env.into_iter().collect();
+
+    Ok(CommandLine::new(expand_path_placeholder(&program), args, cwd, env, shell))
+}
+
+/// 替换`run`、`filter`的程序、参数与工作目录中的`{config_dir}`与`{config_file}`
+fn expand_path_placeholder(s: &str) -> String {
+    s.replace("{config_dir}", &config_dir().display().to_string())
+        .replace("{config_file}", &capslock_forget_config_file_path().display().to_string())
+}
+
+/// 配置文件变化时是否自动重新加载
+/// 
+/// 文件变化时读取，关闭后再开启也能立即生效
+pub fn auto_reload() -> bool {
+    Settings::load().auto_reload
+}
+
+static LAYOUT_AWARE_KEYS: AtomicBool = AtomicBool::new(false);
+
+/// 更新`layout_aware_keys`，加载快捷键之前调用
+/// 
+/// 开启时单个字符的按键名按当前键盘布局解析为输入该字符的按键
+pub fn update_layout_aware_keys(settings: &Settings) {
+    LAYOUT_AWARE_KEYS.store(settings.layout_aware_keys, Ordering::Relaxed);
+}
+
+fn layout_aware_keys() -> bool {
+    LAYOUT_AWARE_KEYS.load(Ordering::Relaxed)
+}
+
 pub fn replace_text_config() -> Result<HashMap<String, String>, ini::Error> {
     let replace_text_file_path = replace_text_file_path();
 
@@ -267,10 +915,83 @@ pub fn replace_text_config() -> Result<HashMap<String, String>, ini::Error> {
     Ok(map)
 }
 
-pub fn shortcut_key_config() -> Result<Result<Option<Result<Vec<Result<ShortcutKeyConfig, ShortcutKeyConfigFileFormatError>>, serde_json::Error>>, yaml_rust2::scanner::ScanError>, std::io::Error> {
-    let capslock_forget_config_file_path = capslock_forget_config_file_path();
+/// 找出重复或被遮蔽的快捷键
+/// 
+/// 监听时按顺序触发第一个匹配的快捷键，排在后面且被前面的快捷键完全覆盖的快捷键永远不会触发
+pub fn binding_conflicts(list: &[ShortcutKeyConfig]) -> Vec<ShortcutKeyConfigFileFormatError> {
+    let mut conflict_list = vec![];
+
+    for (index, config) in list.iter().enumerate() {
+        let Some(earlier) = list[..index].iter().find(|earlier| earlier.shadows(config)) else { continue };
+
+        let explanation = if earlier.same_trigger(config) {
+            format!("duplicate trigger '{}' (already bound by {})", config.trigger_text(), earlier.source)
+        } else {
+            format!("trigger '{}' is shadowed by '{}' ({})", config.trigger_text(), earlier.trigger_text(), earlier.source)
+        };
+
+        conflict_list.push(conflict_error(config, explanation));
+    }
+
+    conflict_list
+}
+
+/// 后加载的文件中的快捷键覆盖先加载的文件中触发按键相同的快捷键时的提示
+pub fn binding_override(earlier: &ShortcutKeyConfig, config: &ShortcutKeyConfig) -> ShortcutKeyConfigFileFormatError {
+    let explanation = format!("duplicate trigger '{}' overrides {}", config.trigger_text(), earlier.source);
+    conflict_error(config, explanation)
+}
+
+fn conflict_error(config: &ShortcutKeyConfig, explanation: String) -> ShortcutKeyConfigFileFormatError {
+    let mut diagnostic = ConfigDiagnostic::new(explanation);
+    diagnostic.file = Some(config.source.file.clone());
+    diagnostic.line = config.source.line;
+    diagnostic.column = config.source.column;
+    diagnostic.binding_index = Some(config.source.index);
+    ShortcutKeyConfigFileFormatError::ConflictError(Box::new(diagnostic))
+}
+
+/// 全部含有快捷键的配置文件，按加载顺序排列
+/// 
+/// 顺序：主配置文件`include`列出的文件 → 主配置文件 → `conf.d`下按文件名排序的`*.yaml`、`*.yml`。
+/// 后加载的文件中的快捷键覆盖先加载的文件中触发按键相同的快捷键，重复列出的文件只加载一次
+pub fn shortcut_key_config_files(settings: &Settings) -> Vec<PathBuf> {
+    let config_dir = config_dir();
+    let mut list = settings.include.clone();
+    list.push(capslock_forget_config_file_path());
+
+    if let Ok(read_dir) = std::fs::read_dir(config_dir.join(CONF_D_DIR)) {
+        let mut conf_d_list = read_dir.flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .filter(|path| matches!(path.extension().and_then(|ext| ext.to_str()), Some("yaml" | "yml")))
+            .collect::<Vec<_>>();
+        conf_d_list.sort();
+        list.extend(conf_d_list);
+    }
+
+    // 同一文件只加载一次，按第一次出现的位置加载
+    let mut loaded = HashSet::new();
+    list.retain(|path| loaded.insert(path.canonicalize().unwrap_or_else(|_| path.clone())));
+    list
+}
+
+/// 单个配置文件中的全部快捷键
+pub struct ShortcutKeyConfigFile {
+    pub list: Vec<Result<ShortcutKeyConfig, ShortcutKeyConfigFileFormatError>>,
+    /// 不影响快捷键加载的问题，例如未使用的字段
+    pub warnings: Vec<ShortcutKeyConfigFileFormatError>,
+}
+
+/// 读取单个配置文件中的快捷键，主配置文件使用`settings`读取时的原文，不再重新读取
+pub fn shortcut_key_config(capslock_forget_config_file_path: &Path, settings: &Settings) -> Result<Result<Option<Result<ShortcutKeyConfigFile, ShortcutKeyConfigFileFormatError>>, yaml_rust2::scanner::ScanError>, std::io::Error> {
+    let capslock_forget_config_file_path = capslock_forget_config_file_path.to_path_buf();
+    let text = match &settings.text {
+        Some(text) if capslock_forget_config_file_path == self::capslock_forget_config_file_path() => text.clone(),
+        _ => read_text_file(&capslock_forget_config_file_path)?,
+    };
     
-    let yaml = match parse_yaml_file(capslock_forget_config_file_path)? {
+    let yaml = match parse_yaml_text(&text)? {
         Ok(yaml) => {
             match yaml {
                 Some(yaml) => yaml,
@@ -282,84 +1003,433 @@ pub fn shortcut_key_config() -> Result<Result<Option<Result<Vec<Result<ShortcutK
 
     let yaml = &yaml[0];
 
-    match yaml["capslock_shortcut"].as_str() {
-        Some(capslock_shortcut_json) => {
-        
-            let mut shortcut_key_config_list = vec![];
-            
+    let entry_list = match &yaml["capslock_shortcut"] {
+        // 旧格式：YAML块字符串内嵌JSON数组
+        Yaml::String(capslock_shortcut_json) => {
             let json: Value = match serde_json::from_str(capslock_shortcut_json) {
                 Ok(json) => json,
-                   Err(err) => return Ok(Ok(Some(Err(err)))),
-            };
-        
-            let json = match json.as_array() {
-                Some(json) => json,
-                None => return Ok(Ok(Some(Ok(Vec::new())))),
+                Err(err) => {
+                    let mut diagnostic = ConfigDiagnostic::new(format!("invalid JSON in 'capslock_shortcut': {}", json_error_message(&err)));
+                    let (line, column) = diagnostic::locate_shortcut(&text).json_position(err.line(), err.column()).unwrap_or((err.line(), err.column()));
+                    diagnostic.file = Some(capslock_forget_config_file_path);
+                    diagnostic.line = Some(line);
+                    diagnostic.column = Some(column);
+                    return Ok(Ok(Some(Err(ShortcutKeyConfigFileFormatError::JsonError(Box::new(diagnostic))))))
+                },
             };
-        
-            for entry in json {
-                let config_entry = ShortcutKeyConfig::from_entry(entry);
-                shortcut_key_config_list.push(config_entry);
-            }
 
-            Ok(Ok(Some(Ok(shortcut_key_config_list))))
+            match json {
+                Value::Array(json) => json,
+                _ => Vec::new(),
+            }
         },
-        None => return Ok(Ok(None)),
+        // 新格式：YAML原生列表
+        Yaml::Array(yaml_list) => yaml_list.iter().map(yaml_to_json).collect(),
+        _ => return Ok(Ok(None)),
+    };
+
+    let mut shortcut_key_config_list = vec![];
+    let mut warnings = vec![];
+    let shortcut_location = diagnostic::locate_shortcut(&text);
+    let unknown_field = settings.unknown_field;
+
+    for (index, entry) in entry_list.iter().enumerate() {
+        let location = shortcut_location.bindings.get(index);
+        let source = BindingSource {
+            file: capslock_forget_config_file_path.clone(),
+            index,
+            line: location.map(|location| location.line),
+            column: location.map(|location| location.column),
+        };
+        let mut entry_warnings = vec![];
+        let config_entry = ShortcutKeyConfig::from_entry(entry, source, unknown_field, &mut entry_warnings).map_err(|mut err| {
+            err.diagnostic_mut().locate(&capslock_forget_config_file_path, index, location);
+            err
+        });
+        for mut warning in entry_warnings {
+            warning.diagnostic_mut().locate(&capslock_forget_config_file_path, index, location);
+            warnings.push(warning);
+        }
+        shortcut_key_config_list.push(config_entry);
+    }
+
+    Ok(Ok(Some(Ok(ShortcutKeyConfigFile {
+        list: shortcut_key_config_list,
+        warnings,
+    }))))
+}
+
+/// serde_json的错误信息，去掉末尾的位置，位置由调用方换算后另行给出
+fn json_error_message(err: &serde_json::Error) -> String {
+    let message = err.to_string();
+    match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message,
     }
 }
 
+/// 把YAML节点转换为JSON值，使原生YAML列表与旧的JSON字符串共用同一套解析
+fn yaml_to_json(yaml: &Yaml) -> Value {
+    match yaml {
+        Yaml::Real(real) => {
+            real.parse::<f64>().ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number)
+                .unwrap_or(Value::Null)
+        },
+        Yaml::Integer(integer) => Value::from(*integer),
+        Yaml::String(string) => Value::String(string.clone()),
+        Yaml::Boolean(boolean) => Value::Bool(*boolean),
+        Yaml::Array(array) => Value::Array(array.iter().map(yaml_to_json).collect()),
+        Yaml::Hash(hash) => {
+            let mut map = serde_json::Map::new();
+            for (k, v) in hash.iter() {
+                let k = match k {
+                    Yaml::String(k) | Yaml::Real(k) => k.clone(),
+                    Yaml::Integer(k) => k.to_string(),
+                    Yaml::Boolean(k) => k.to_string(),
+                    _ => continue,
+                };
+                map.insert(k, yaml_to_json(v));
+            }
+            Value::Object(map)
+        },
+        Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => Value::Null,
+    }
+}
 
+/// 解析按键组合，例如`Ctrl + Shift + K`
+/// 
+/// 修饰键与按键的顺序不限，`+`前后的空白可以省略。需要`+`按键时直接书写，例如`Ctrl + +`，也可以写作`Plus`。
+/// 只有修饰键时，最后一个修饰键作为按键，例如`Ctrl + Shift`表示按住Ctrl时按下Shift
 pub fn parse_shortcut_key_text<S: Into<String>>(s: S) -> Result<(Key, ModifierKey), ShortcutKeyConfigFileFormatError> {
     let s: String = s.into();
-    let s = &*s;
-    let mut key_list = vec![];
+    parse_chord(&s, false)
+}
+
+/// 解析快捷键的触发按键，可以是单个按键组合或按键序列
+/// 
+/// 只有一项时按`,`拆分为序列，例如`Caps + W, Caps + S`，多项时每项是序列中的一步。
+/// 第一步总是按住`Capslock`按下，`Caps`可以省略；之后写明`Caps`的一步按住`Capslock`按下，
+/// 没有`Caps`的一步在松开`Capslock`后按下，例如前导键序列`Caps + G, H`
+fn parse_key_sequence(list: &[String]) -> Result<Vec<SequenceStep>, ShortcutKeyConfigFileFormatError> {
+    let step_list = match list {
+        [s] => split_sequence(s),
+        list => list.iter().map(|s| s.as_str()).collect(),
+    };
+
+    step_list.into_iter().enumerate()
+        .map(|(index, step)| {
+            let (key, modifier_key) = parse_chord(step, true)?;
+            // 无法拆分的步骤已由`parse_chord`报告
+            let token_list = split_chord(step).unwrap_or_default();
+            let hold_caps = index == 0 || token_list.iter().any(|token| Key::from_str(token) == Ok(Key::CapsLock));
+            if index > 0 && key == Key::CapsLock && token_list.len() == 1 {
+                let explanation = format!("missing key in step '{}' of a key sequence, write 'Caps + <key>' or a key without 'Caps'", step.trim());
+                return Err(ShortcutKeyConfigFileFormatError::KeyError(Box::new(ConfigDiagnostic::new(explanation))))
+            }
+            Ok(SequenceStep { key, modifier_key, hold_caps })
+        })
+        .collect()
+}
 
-    let original_key_str = s.to_string();
-    let key_str_list = s.split("+").map(|s| s.trim()).collect::<Vec<_>>();
+/// `trigger`为`true`时忽略与其他按键一起书写的`Caps`
+fn parse_chord(s: &str, trigger: bool) -> Result<(Key, ModifierKey), ShortcutKeyConfigFileFormatError> {
+    let key_error = |explanation: String| ShortcutKeyConfigFileFormatError::KeyError(Box::new(ConfigDiagnostic::new(explanation)));
+
+    let mut token_list = split_chord(s).map_err(key_error)?;
+    if trigger && token_list.len() > 1 {
+        token_list.retain(|token| Key::from_str(token) != Ok(Key::CapsLock));
+    }
 
     let mut modifier_key = ModifierKey::new();
-    
-    let mut key = None;
+    let mut modifier_list = vec![];
+    let mut key = None::<(Key, &str)>;
+    let mut implied = ModifierKey::new();
 
-    if key_str_list.len() == 1 {
-        match Key::from_str(key_str_list[0]) {
-            Ok(key_) => key = Some(key_),
-            Err(_) => return Err(ShortcutKeyConfigFileFormatError::KeyError(key_str_list[0].to_string())),
+    for token in token_list.iter().copied() {
+        if modifier_key.set(&token.to_ascii_lowercase()) {
+            modifier_list.push(token);
+            continue;
         }
-    } else {
-        for key_str in key_str_list {
-            let key = {
-                match key_str.to_ascii_lowercase().as_str() {
-                    "ctrl" => modifier_key.ctrl = true,
-                    "shift" => modifier_key.shift = true,
-                    "alt" => modifier_key.alt = true,
-                    "meta" => modifier_key.meta = true,
-                    other_key => {
-                        match Key::from_str(other_key) {
-                            Ok(key_) => {
-                                key = Some(key_);
-                            },
-                            Err(_) => {
-                                return Err(ShortcutKeyConfigFileFormatError::KeyError(original_key_str))
-                            },
-                        }
-                        break;
-                    },
-                };
+
+        if let Some(name) = token.strip_prefix('*') {
+            return Err(key_error(wildcard_error(token, name)));
+        }
+
+        let key_ = match char_key(token).map_err(key_error)? {
+            Some((key_, implied_)) => {
+                implied = implied_;
+                key_
+            },
+            None => match Key::from_str(token) {
+                Ok(key_) => {
+                    // `{`等符号按美式键盘布局隐含Shift，`[`等按键名不变
+                    if key_from_str::is_shifted_symbol(token) {
+                        implied.shift = Modifier::Either;
+                    }
+                    key_
+                },
+                Err(_) => return Err(unknown_key_error(token)),
+            },
+        };
+
+        if let Some((_, first)) = key {
+            return Err(key_error(format!("more than one key in '{s}': '{first}' and '{token}'")));
+        }
+        key = Some((key_, token));
+    }
+
+    let key = match key {
+        Some((key, _)) => {
+            modifier_key.imply(&implied);
+            key
+        },
+        None => {
+            // 只有修饰键时，最后一个修饰键作为按键
+            let Some(last) = modifier_list.pop() else {
+                return Err(key_error(format!("no key in '{s}'")))
             };
-    
-            key_list.push(key);
+            if last.starts_with('*') {
+                return Err(key_error(format!("missing key in '{s}': '{last}' only loosens which modifiers may be held, add the key to press, e.g. '{last} + K'")))
+            }
+
+            let mut last_modifier_key = ModifierKey::new();
+            last_modifier_key.set(&last.to_ascii_lowercase());
+            let key = match Key::from_str(last) {
+                Ok(key) => Some(key),
+                Err(_) => last_modifier_key.keys().first().copied(),
+            };
+            let Some(key) = key else {
+                return Err(key_error(format!("no key in '{s}'")))
+            };
+
+            modifier_key = ModifierKey::new();
+            for modifier in modifier_list {
+                modifier_key.set(&modifier.to_ascii_lowercase());
+            }
+            key
+        },
+    };
+
+    Ok((key, modifier_key))
+}
+
+/// 按字符指定按键的前缀，例如`char:{`
+pub static CHAR_KEY_PREFIX: &str = "char:";
+
+/// 按当前键盘布局解析字符按键，返回按键与输入该字符所需的修饰键
+/// 
+/// `char:X`总是按字符解析。开启`layout_aware_keys`时，单个字符的按键名也按字符解析，
+/// 字母不区分大小写，布局中没有该字符时仍按按键名解析。不是字符按键时返回`None`
+fn char_key(token: &str) -> Result<Option<(Key, ModifierKey)>, String> {
+    let (c, explicit) = match strip_char_key_prefix(token) {
+        Some(c) => {
+            let mut chars = c.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => (c, true),
+                _ => return Err(format!("'{token}' must name exactly one character")),
+            }
+        },
+        None => {
+            let mut chars = token.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if layout_aware_keys() => (c.to_ascii_lowercase(), false),
+                _ => return Ok(None),
+            }
+        },
+    };
+
+    match keyboard::key_for_char(c) {
+        Some(char_key) => {
+            let mut implied = ModifierKey::new();
+            if char_key.shift {
+                implied.shift = Modifier::Either;
+            }
+            // Ctrl + Alt即AltGr
+            if char_key.ctrl && char_key.alt {
+                implied.ctrl = Modifier::Either;
+                implied.alt = Modifier::Right;
+            } else if char_key.ctrl {
+                implied.ctrl = Modifier::Either;
+            } else if char_key.alt {
+                implied.alt = Modifier::Either;
+            }
+            Ok(Some((char_key.key, implied)))
+        },
+        None if explicit => Err(format!("character '{c}' cannot be typed with the current keyboard layout")),
+        None => Ok(None),
+    }
+}
+
+fn strip_char_key_prefix(token: &str) -> Option<&str> {
+    let prefix = token.get(..CHAR_KEY_PREFIX.len())?;
+    prefix.eq_ignore_ascii_case(CHAR_KEY_PREFIX).then(|| &token[CHAR_KEY_PREFIX.len()..])
+}
+
+/// 按`,`拆分按键序列
+/// 
+/// 紧跟在`+`、`char:`之后或位于一步开头的`,`视为`,`按键本身，例如`Caps + ,, Caps + .`
+fn split_sequence(s: &str) -> Vec<&str> {
+    let mut step_list = vec![];
+    let mut start = 0;
+    let mut expect_key = true;
+
+    for (index, c) in s.char_indices() {
+        if c.is_whitespace() {
+            continue;
         }
-    
-        if key.is_none() {
-            return Err(ShortcutKeyConfigFileFormatError::KeyError(original_key_str));
+        if c == ',' && !expect_key && !s[..index].to_ascii_lowercase().ends_with(CHAR_KEY_PREFIX) {
+            step_list.push(&s[start..index]);
+            start = index + 1;
+            expect_key = true;
+            continue;
+        }
+        expect_key = c == '+';
+    }
+    step_list.push(&s[start..]);
+
+    step_list
+}
+
+/// 按`+`拆分按键组合
+/// 
+/// 应当出现按键的位置上的`+`视为`+`按键本身，缺少按键或两个按键之间缺少`+`时返回错误说明
+fn split_chord(s: &str) -> Result<Vec<&str>, String> {
+    let mut token_list = vec![];
+    let mut rest = s.trim_start();
+
+    if rest.is_empty() {
+        return Err("empty key".to_string())
+    }
+
+    loop {
+        // 读取一个按键
+        let token = if rest.starts_with('+') {
+            &rest[..1]
+        } else if let Some(c) = strip_char_key_prefix(rest).and_then(|c| c.chars().next()) {
+            // `char:`后的一个字符可以是`+`或空白
+            &rest[..CHAR_KEY_PREFIX.len() + c.len_utf8()]
+        } else {
+            let end = rest.find(|c: char| c == '+' || c.is_whitespace()).unwrap_or(rest.len());
+            &rest[..end]
+        };
+        token_list.push(token);
+        rest = rest[token.len()..].trim_start();
+
+        if rest.is_empty() {
+            return Ok(token_list)
+        }
+
+        // 读取分隔的`+`
+        match rest.strip_prefix('+') {
+            Some(next) => rest = next.trim_start(),
+            None => {
+                let next = rest.split(|c: char| c == '+' || c.is_whitespace()).next().unwrap_or(rest);
+                return Err(format!("missing '+' between '{token}' and '{next}' in '{s}'"))
+            },
         }
+
+        if rest.is_empty() {
+            return Err(format!("missing key after the last '+' in '{s}'"))
+        }
+    }
+}
+
+/// `*`开头但不是不限修饰键时的说明
+fn wildcard_error(token: &str, name: &str) -> String {
+    let lower_name = name.to_ascii_lowercase();
+    match MODIFIER_TABLE.iter().find(|(modifier_name, _, _)| *modifier_name == lower_name) {
+        Some((_, slot, _)) => {
+            let side_less = match slot {
+                ModifierSlot::Ctrl => "Ctrl",
+                ModifierSlot::Shift => "Shift",
+                ModifierSlot::Alt => "Alt",
+                ModifierSlot::Meta => "Meta",
+            };
+            format!("'*' only applies to modifiers without a side, write '*{side_less}' instead of '{token}'")
+        },
+        None => format!("'*' only applies to modifiers without a side such as '*Ctrl', '{name}' is not one"),
     }
+}
 
-    Ok((key.unwrap(), modifier_key))
+fn unknown_key_error(key_str: &str) -> ShortcutKeyConfigFileFormatError {
+    let diagnostic = ConfigDiagnostic::new(format!("unknown key name '{key_str}'"))
+        .with_suggestion(suggest_key_name(key_str));
+    ShortcutKeyConfigFileFormatError::KeyError(Box::new(diagnostic))
 }
 
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_chord_tokens() {
+        assert_eq!(split_chord("Ctrl + Shift + K").unwrap(), ["Ctrl", "Shift", "K"]);
+        assert_eq!(split_chord("  K+Ctrl ").unwrap(), ["K", "Ctrl"]);
+        assert_eq!(split_chord("Caps").unwrap(), ["Caps"]);
+    }
+
+    #[test]
+    fn split_chord_plus_key() {
+        assert_eq!(split_chord("+").unwrap(), ["+"]);
+        assert_eq!(split_chord("Ctrl + +").unwrap(), ["Ctrl", "+"]);
+        assert_eq!(split_chord("Ctrl++").unwrap(), ["Ctrl", "+"]);
+        assert_eq!(split_chord("+ + Ctrl").unwrap(), ["+", "Ctrl"]);
+    }
+
+    #[test]
+    fn split_chord_char_keys() {
+        assert_eq!(split_chord("Ctrl + char:+").unwrap(), ["Ctrl", "char:+"]);
+        assert_eq!(split_chord("char: + Alt").unwrap(), ["char: ", "Alt"]);
+        assert_eq!(split_chord("CHAR:{").unwrap(), ["CHAR:{"]);
+    }
+
+    #[test]
+    fn split_chord_errors() {
+        assert_eq!(split_chord("").unwrap_err(), "empty key");
+        assert_eq!(split_chord("   ").unwrap_err(), "empty key");
+        assert_eq!(split_chord("Ctrl K").unwrap_err(), "missing '+' between 'Ctrl' and 'K' in 'Ctrl K'");
+        assert_eq!(split_chord("Ctrl + ").unwrap_err(), "missing key after the last '+' in 'Ctrl + '");
+        assert_eq!(split_chord("Ctrl ++ +").unwrap_err(), "missing key after the last '+' in 'Ctrl ++ +'");
+        assert_eq!(split_chord("Ctrl + + K").unwrap_err(), "missing '+' between '+' and 'K' in 'Ctrl + + K'");
+    }
+
+    #[test]
+    fn parse_chord_wildcard_errors() {
+        let explanation = |s: &str| parse_chord(s, true).unwrap_err().diagnostic().explanation.clone();
+        assert_eq!(explanation("*LCtrl + K"), "'*' only applies to modifiers without a side, write '*Ctrl' instead of '*LCtrl'");
+        assert_eq!(explanation("*Ctrl"), "missing key in '*Ctrl': '*Ctrl' only loosens which modifiers may be held, add the key to press, e.g. '*Ctrl + K'");
+        assert_eq!(explanation("*K + J"), "'*' only applies to modifiers without a side such as '*Ctrl', 'K' is not one");
+        assert!(parse_chord("*Ctrl + K", true).is_ok());
+    }
 
+    #[test]
+    fn split_sequence_steps() {
+        assert_eq!(split_sequence("Caps + W"), ["Caps + W"]);
+        assert_eq!(split_sequence("Caps + G, Caps + H"), ["Caps + G", " Caps + H"]);
+        assert_eq!(split_sequence("Caps+G,Caps+H,Caps+J"), ["Caps+G", "Caps+H", "Caps+J"]);
+    }
 
+    #[test]
+    fn split_sequence_comma_key() {
+        // `+`之后、`char:`之后或一步开头的`,`是按键本身
+        assert_eq!(split_sequence("Caps + ,, Caps + ."), ["Caps + ,", " Caps + ."]);
+        assert_eq!(split_sequence(",, Caps + ."), [",", " Caps + ."]);
+        assert_eq!(split_sequence("Caps + char:,, Caps + char:,"), ["Caps + char:,", " Caps + char:,"]);
+        assert_eq!(split_sequence("Caps + G,"), ["Caps + G", ""]);
+    }
 
+    #[test]
+    fn key_sequence_leader_steps() {
+        let hold_caps = |list: &[&str]| {
+            let list = list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+            parse_key_sequence(&list).unwrap().iter().map(|step| (step.key, step.hold_caps)).collect::<Vec<_>>()
+        };
+        assert_eq!(hold_caps(&["Caps + G, H"]), [(Key::KeyG, true), (Key::KeyH, false)]);
+        assert_eq!(hold_caps(&["Caps+G", "H"]), [(Key::KeyG, true), (Key::KeyH, false)]);
+        assert_eq!(hold_caps(&["G, Caps + H"]), [(Key::KeyG, true), (Key::KeyH, true)]);
+        assert!(matches!(parse_key_sequence(&["Caps + G, Caps".to_string()]), Err(ShortcutKeyConfigFileFormatError::KeyError(_))));
+    }
+}
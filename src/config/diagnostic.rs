@@ -0,0 +1,381 @@
+use std::path::{Path, PathBuf};
+
+use yaml_rust2::{parser::{Event, MarkedEventReceiver, Parser}, scanner::Marker};
+
+
+/// 配置错误的详细信息
+///
+/// 记录错误说明与建议，定位后再补充文件、行列与出错的快捷键序号
+#[derive(Debug, Clone, Default)]
+pub struct ConfigDiagnostic {
+    /// 人类可读的错误说明
+    pub explanation: String,
+    /// "did you mean"建议
+    pub suggestion: Option<String>,
+    /// 出错的字段，用于定位到具体的行
+    pub field: Option<String>,
+    pub file: Option<PathBuf>,
+    /// 行号，从1开始
+    pub line: Option<usize>,
+    /// 列号，从1开始
+    pub column: Option<usize>,
+    /// 出错的快捷键在`capslock_shortcut`中的序号，从0开始
+    pub binding_index: Option<usize>,
+}
+
+impl ConfigDiagnostic {
+    pub fn new<E: Into<String>>(explanation: E) -> Self {
+        Self {
+            explanation: explanation.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_suggestion(mut self, suggestion: Option<String>) -> Self {
+        self.suggestion = suggestion;
+        self
+    }
+
+    pub fn with_field<S: Into<String>>(mut self, field: S) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+
+    /// 补充文件与位置信息，已有的信息不会被覆盖
+    pub fn locate(&mut self, file: &Path, binding_index: usize, location: Option<&BindingLocation>) {
+        if self.file.is_none() {
+            self.file = Some(file.to_path_buf());
+        }
+
+        if self.binding_index.is_none() {
+            self.binding_index = Some(binding_index);
+        }
+
+        if self.line.is_none() {
+            if let Some(location) = location {
+                let (line, column) = self.field.as_deref()
+                    .and_then(|field| location.field(field))
+                    .unwrap_or((location.line, location.column));
+                self.line = Some(line);
+                self.column = Some(column);
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}", file.display())?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+                if let Some(column) = self.column {
+                    write!(f, ":{}", column)?;
+                }
+            }
+            write!(f, ": ")?;
+        }
+
+        write!(f, "{}", self.explanation)?;
+
+        if let Some(binding_index) = self.binding_index {
+            write!(f, " in binding #{}", binding_index + 1)?;
+        }
+
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean '{}'?)", suggestion)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+/// 单个快捷键在配置文件中的位置
+#[derive(Debug, Clone, Default)]
+pub struct BindingLocation {
+    pub line: usize,
+    pub column: usize,
+    /// 字段名与字段值的位置
+    pub fields: Vec<(String, usize, usize)>,
+}
+
+impl BindingLocation {
+    pub fn field(&self, name: &str) -> Option<(usize, usize)> {
+        self.fields.iter()
+            .find(|(field, _, _)| field == name)
+            .map(|(_, line, column)| (*line, *column))
+    }
+}
+
+/// 配置文件中`capslock_shortcut`的位置信息
+#[derive(Debug, Default)]
+pub struct ShortcutLocation {
+    /// 每个快捷键的位置，下标与`capslock_shortcut`中的序号一致
+    pub bindings: Vec<BindingLocation>,
+    /// 旧格式下JSON字符串内容在文件中的起始行（从1开始）与缩进
+    pub json_origin: Option<(usize, usize)>,
+}
+
+impl ShortcutLocation {
+    /// 把JSON字符串内的行列（从1开始）换算为配置文件中的行列
+    pub fn json_position(&self, line: usize, column: usize) -> Option<(usize, usize)> {
+        self.json_origin.map(|(origin_line, indent)| (origin_line + line - 1, indent + column))
+    }
+}
+
+/// 扫描配置文件，找出`capslock_shortcut`中每个快捷键所在的行列
+///
+/// 解析失败时返回空的位置信息，只影响错误提示的精确度
+pub fn locate_shortcut(text: &str) -> ShortcutLocation {
+    let mut locator = Locator::default();
+    let mut parser = Parser::new_from_str(text);
+    if parser.load(&mut locator, false).is_err() {
+        return ShortcutLocation::default();
+    }
+
+    let mut location = ShortcutLocation {
+        bindings: locator.bindings,
+        json_origin: None,
+    };
+
+    if let Some((json, key_mark)) = locator.json {
+        // 块字符串的内容从`capslock_shortcut: |`的下一行开始
+        let origin_line = key_mark.line() + 1;
+        let indent = text.lines()
+            .skip(origin_line - 1)
+            .find(|line| !line.trim().is_empty())
+            .map(|line| line.len() - line.trim_start().len())
+            .unwrap_or(0);
+        location.json_origin = Some((origin_line, indent));
+
+        location.bindings = locate_json_bindings(&json).into_iter()
+            .map(|mut binding| {
+                (binding.line, binding.column) = location.json_position(binding.line, binding.column).unwrap();
+                for (_, line, column) in binding.fields.iter_mut() {
+                    (*line, *column) = location.json_position(*line, *column).unwrap();
+                }
+                binding
+            })
+            .collect();
+    }
+
+    location
+}
+
+enum Frame {
+    Sequence,
+    Mapping {
+        expect_key: bool,
+        key: Option<String>,
+    },
+}
+
+#[derive(Default)]
+struct Locator {
+    stack: Vec<Frame>,
+    /// `capslock_shortcut`列表所在的层级
+    shortcut_depth: Option<usize>,
+    bindings: Vec<BindingLocation>,
+    /// 旧格式的JSON字符串，以及`capslock_shortcut`键的位置
+    json: Option<(String, Marker)>,
+    key_mark: Option<Marker>,
+}
+
+impl Locator {
+    fn node(&mut self, scalar: Option<&str>, is_sequence: bool, mark: Marker) {
+        let depth = self.stack.len();
+        let shortcut_depth = self.shortcut_depth;
+
+        match self.stack.last_mut() {
+            Some(Frame::Mapping { expect_key, key }) => {
+                if *expect_key {
+                    *key = scalar.map(|s| s.to_string());
+                    *expect_key = false;
+
+                    if depth == 1 {
+                        self.key_mark = Some(mark);
+                    } else if shortcut_depth.map(|d| d + 2) == Some(depth) {
+                        // 以第一个字段名的位置作为快捷键的位置
+                        if let Some(binding) = self.bindings.last_mut().filter(|binding| binding.fields.is_empty()) {
+                            binding.line = mark.line();
+                            binding.column = mark.col() + 1;
+                        }
+                    }
+                    return;
+                }
+                *expect_key = true;
+
+                let key = key.as_deref();
+                if depth == 1 && key == Some("capslock_shortcut") {
+                    if let (Some(json), Some(key_mark)) = (scalar, self.key_mark) {
+                        self.json = Some((json.to_string(), key_mark));
+                    } else if is_sequence {
+                        self.shortcut_depth = Some(depth);
+                    }
+                } else if shortcut_depth.map(|d| d + 2) == Some(depth) {
+                    if let (Some(binding), Some(key)) = (self.bindings.last_mut(), key) {
+                        binding.fields.push((key.to_string(), mark.line(), mark.col() + 1));
+                    }
+                }
+            },
+            Some(Frame::Sequence) if shortcut_depth.map(|d| d + 1) == Some(depth) => {
+                self.bindings.push(BindingLocation {
+                    line: mark.line(),
+                    column: mark.col() + 1,
+                    fields: vec![],
+                });
+            },
+            Some(Frame::Sequence) | None => (),
+        }
+    }
+}
+
+impl MarkedEventReceiver for Locator {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        match ev {
+            Event::Scalar(value, ..) => self.node(Some(&value), false, mark),
+            Event::Alias(_) => self.node(None, false, mark),
+            Event::SequenceStart(..) => {
+                self.node(None, true, mark);
+                self.stack.push(Frame::Sequence);
+            },
+            Event::MappingStart(..) => {
+                self.node(None, false, mark);
+                self.stack.push(Frame::Mapping { expect_key: true, key: None });
+            },
+            Event::SequenceEnd | Event::MappingEnd => {
+                self.stack.pop();
+            },
+            _ => (),
+        }
+    }
+}
+
+/// 扫描JSON数组文本，找出每个元素及其字段值的行列（从1开始）
+fn locate_json_bindings(json: &str) -> Vec<BindingLocation> {
+    let mut bindings: Vec<BindingLocation> = vec![];
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escape = false;
+    let mut string = String::new();
+    let mut last_string = None::<String>;
+    // 当前位置是否可以开始一个数组元素或字段值
+    let mut expect_value = false;
+    let (mut line, mut column) = (1, 0);
+
+    for c in json.chars() {
+        if c == '\n' {
+            line += 1;
+            column = 0;
+        } else {
+            column += 1;
+        }
+
+        if in_string {
+            if escape {
+                escape = false;
+            } else if c == '\\' {
+                escape = true;
+            } else if c == '"' {
+                in_string = false;
+                last_string = Some(std::mem::take(&mut string));
+            } else {
+                string.push(c);
+            }
+            continue;
+        }
+
+        if c.is_whitespace() {
+            continue;
+        }
+
+        if expect_value {
+            expect_value = false;
+            if depth == 1 {
+                bindings.push(BindingLocation { line, column, fields: vec![] });
+            } else if depth == 2 {
+                if let (Some(binding), Some(key)) = (bindings.last_mut(), last_string.take()) {
+                    binding.fields.push((key, line, column));
+                }
+            }
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                string.clear();
+            },
+            '[' | '{' => {
+                depth += 1;
+                expect_value = depth == 1;
+            },
+            ']' | '}' => depth -= 1,
+            ',' => expect_value = depth == 1,
+            ':' => expect_value = depth == 2,
+            _ => (),
+        }
+    }
+
+    bindings
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate_yaml_list() {
+        let text = [
+            "sequence_timeout: 1000",
+            "capslock_shortcut:",
+            "  - key: Caps + W",
+            "    feature: input_text",
+            "    text: hi",
+            "  - feature: caps_toggle",
+            "    key: [Caps + G, Caps + H]",
+        ].join("\n");
+        let location = locate_shortcut(&text);
+
+        assert_eq!(location.json_origin, None);
+        assert_eq!(location.bindings.len(), 2);
+        assert_eq!((location.bindings[0].line, location.bindings[0].column), (3, 5));
+        assert_eq!(location.bindings[0].field("key"), Some((3, 10)));
+        assert_eq!(location.bindings[0].field("feature"), Some((4, 14)));
+        assert_eq!(location.bindings[0].field("text"), Some((5, 11)));
+        assert_eq!((location.bindings[1].line, location.bindings[1].column), (6, 5));
+        assert_eq!(location.bindings[1].field("key"), Some((7, 10)));
+        assert_eq!(location.bindings[1].field("text"), None);
+    }
+
+    #[test]
+    fn locate_json_string() {
+        let text = [
+            "capslock_shortcut: |",
+            "  [",
+            "    {\"key\": \"Caps + W\", \"feature\": \"caps_toggle\"},",
+            "    {",
+            "      \"key\": \"Caps + S\"",
+            "    }",
+            "  ]",
+        ].join("\n");
+        let location = locate_shortcut(&text);
+
+        assert_eq!(location.json_origin, Some((2, 2)));
+        assert_eq!(location.json_position(1, 1), Some((2, 3)));
+        assert_eq!(location.bindings.len(), 2);
+        assert_eq!((location.bindings[0].line, location.bindings[0].column), (3, 5));
+        assert_eq!(location.bindings[0].field("key"), Some((3, 13)));
+        assert_eq!(location.bindings[0].field("feature"), Some((3, 36)));
+        assert_eq!((location.bindings[1].line, location.bindings[1].column), (4, 5));
+        assert_eq!(location.bindings[1].field("key"), Some((5, 14)));
+    }
+
+    #[test]
+    fn locate_invalid_yaml() {
+        let location = locate_shortcut("capslock_shortcut: [\n  - key: {");
+        assert!(location.bindings.is_empty());
+        assert_eq!(location.json_origin, None);
+    }
+}
@@ -1,123 +1,242 @@
 use caps_shortcut::Key;
 
+use crate::units::{keyboard, string::did_you_mean};
+
 pub trait KeyFromStr: Sized {
     fn from_str(s: &str) -> Result<Self, ()>;
 }
 
 impl KeyFromStr for Key {
     fn from_str(s: &str) -> Result<Self, ()> {
-        use caps_shortcut::Key::*;
-
-        let key = match s.to_ascii_lowercase().trim_matches('"') {
-            "alt" => Alt,
-            "altgr" => AltGr,
-            "backspace" | "back" => Backspace,
-            "capslock" | "caps" => CapsLock,
-            "controlleft" | "ctrl" => ControlLeft,
-            "controlright" => ControlRight,
-            "delete" | "del" => Delete,
-            "downarrow" | "arrowdown" => DownArrow,
-            "end" => End,
-            "escape" | "esc" => Escape,
-            "f1" => F1,
-            "f2" => F2,
-            "f3" => F3,
-            "f4" => F4,
-            "f5" => F5,
-            "f6" => F6,
-            "f7" => F7,
-            "f8" => F8,
-            "f9" => F9,
-            "f10" => F10,
-            "f11" => F11,
-            "f12" => F12,
-            "home" => Home,
-            "leftarrow" | "arrowleft" => LeftArrow,
-            "metaleft" | "meta" | "win" => MetaLeft,
-            "pagedown" => PageDown,
-            "pageup" => PageUp,
-            "return" | "enter" => Return,
-            "rightarrow" | "arrowright" => RightArrow,
-            "shiftleft" | "shift" => ShiftLeft,
-            "shiftright" => ShiftRight,
-            "space" => Space,
-            "tab" => Tab,
-            "uparrow" | "arrowup" => UpArrow,
-            "printscreen" | "prtsc" => PrintScreen,
-            "scrolllock" => ScrollLock,
-            "pause" => Pause,
-            "numlock" => NumLock,
-            "`" | "~" | "backquote" => BackQuote,
-            "1" | "num1" => Num1,
-            "2" | "num2" => Num2,
-            "3" | "num3" => Num3,
-            "4" | "num4" => Num4,
-            "5" | "num5" => Num5,
-            "6" | "num6" => Num6,
-            "7" | "num7" => Num7,
-            "8" | "num8" => Num8,
-            "9" | "num9" => Num9,
-            "0" | "num0" => Num0,
-            "-" | "_" | "minus" => Minus,
-            "=" | "+" | "equal" => Equal,
-            "q" | "keyq" => KeyQ,
-            "w" | "keyw" => KeyW,
-            "e" | "keye" => KeyE,
-            "r" | "keyr" => KeyR,
-            "t" | "keyt" => KeyT,
-            "y" | "keyy" => KeyY,
-            "u" | "keyu" => KeyU,
-            "i" | "keyi" => KeyI,
-            "o" | "keyo" => KeyO,
-            "p" | "keyp" => KeyP,
-            "[" | "{" | "leftbracket" => LeftBracket,
-            "]" | "}" | "rightbracket" => RightBracket,
-            "a" | "keya" => KeyA,
-            "s" | "keys" => KeyS,
-            "d" | "keyd" => KeyD,
-            "f" | "keyf" => KeyF,
-            "g" | "keyg" => KeyG,
-            "h" | "keyh" => KeyH,
-            "j" | "keyj" => KeyJ,
-            "k" | "keyk" => KeyK,
-            "l" | "keyl" => KeyL,
-            ";" | ":" | "semicolon" => SemiColon,
-            "'" | "\"" | "quote" => Quote,
-            "\\" | "|" | "backslash" => BackSlash,
-            "intlbackslash" => IntlBackslash,
-            "z" | "keyz" => KeyZ,
-            "x" | "keyx" => KeyX,
-            "c" | "keyc" => KeyC,
-            "v" | "keyv" => KeyV,
-            "b" | "keyb" => KeyB,
-            "n" | "keyn" => KeyN,
-            "m" | "keym" => KeyM,
-            "," | "<" | "comma" => Comma,
-            "." | ">" | "dot" => Dot,
-            "/" | "?" | "slash" => Slash,
-            "insert" | "ins" => Insert,
-            //KP_RETURN, 13,
-            "kpminus" => KpMinus,
-            "kpplus" => KpPlus,
-            "kpmultiply" => KpMultiply,
-            "kpdivide" => KpDivide,
-            "kp0" => Kp0,
-            "kp1" => Kp1,
-            "kp2" => Kp2,
-            "kp3" => Kp3,
-            "kp4" => Kp4,
-            "kp5" => Kp5,
-            "kp6" => Kp6,
-            "kp7" => Kp7,
-            "kp8" => Kp8,
-            "kp9" => Kp9,
-            "kpdelete" => KpDelete,
-            "metaright" => MetaRight,
-            "kpreturn" => KpReturn,
-            "function" => Function,
-            _ => return Err(())
-        };
-        
-        Ok(key)
+        let s = s.to_ascii_lowercase();
+        let s = if s == "\"" { &s } else { s.trim_matches('"') };
+        match key_from_raw_code(s) {
+            Some(key) => key,
+            None => key_from_lower_str(s).ok_or(()),
+        }
     }
-}
\ No newline at end of file
+}
+
+/// 美式键盘布局下需要按住Shift输入的符号，以及`+`的名字`plus`
+static SHIFTED_SYMBOLS: &[&str] = &[
+    "~", "!", "@", "#", "$", "%", "^", "&", "(", ")", "_", "+", "plus",
+    "{", "}", "|", ":", "\"", "<", ">", "?",
+];
+
+/// 按键名是否为需要Shift的符号，例如`{`表示`Shift + [`
+pub fn is_shifted_symbol(s: &str) -> bool {
+    SHIFTED_SYMBOLS.contains(&s.to_ascii_lowercase().as_str())
+}
+
+/// 原始按键码的前缀，`vk:`为虚拟键码，`sc:`为扫描码
+pub static RAW_CODE_PREFIXES: &[&str] = &["vk:", "sc:"];
+
+/// 解析`vk:0xAD`、`sc:0x1D`形式的原始按键码，不是原始按键码时返回`None`
+fn key_from_raw_code(s: &str) -> Option<Result<Key, ()>> {
+    let (prefix, code) = RAW_CODE_PREFIXES.iter()
+        .find_map(|prefix| s.strip_prefix(prefix).map(|code| (*prefix, code.trim())))?;
+
+    let code = match code.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => code.parse::<u32>(),
+    };
+    let Ok(code) = code else {
+        return Some(Err(()))
+    };
+
+    let key = match prefix {
+        "vk:" if (1..=0xFE).contains(&code) => Some(keyboard::key_from_virtual_key(code as u16)),
+        "sc:" => keyboard::key_from_scan_code(code),
+        _ => None,
+    };
+    Some(key.ok_or(()))
+}
+
+/// 按键的展示名，没有专门名字的按键使用表中的第一个名字，或`vk:0x..`形式
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Unknown(vk) => KEY_NAMES.iter()
+            .find(|name| key_from_lower_str(name) == Some(key))
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| format!("vk:0x{:02X}", vk)),
+        key => format!("{:?}", key),
+    }
+}
+
+/// 找出与输入最接近的按键名
+pub fn suggest_key_name(s: &str) -> Option<String> {
+    let name = did_you_mean(s, KEY_NAMES.iter().copied())?;
+    Some(display_key_name(name))
+}
+
+/// 按键名的展示写法
+/// 
+/// 与按键本身的名字相同时（如`quote`与`Key::Quote`），返回按键的正式写法`Quote`
+pub fn display_key_name(name: &str) -> String {
+    let key_name = key_from_lower_str(name).map(|key| format!("{:?}", key));
+    match key_name {
+        Some(key_name) if key_name.to_ascii_lowercase() == name => key_name,
+        _ => name.to_string(),
+    }
+}
+
+/// 同时生成按键查找函数与全部按键名列表，保证两者一致
+macro_rules! key_table {
+    ($($($name:literal)|+ => $key:expr,)*) => {
+        /// 全部可用的按键名（小写）
+        pub static KEY_NAMES: &[&str] = &[$($($name,)+)*];
+
+        fn key_from_lower_str(s: &str) -> Option<Key> {
+            use caps_shortcut::Key::*;
+
+            let key = match s {
+                $($($name)|+ => $key,)*
+                _ => return None,
+            };
+
+            Some(key)
+        }
+    };
+}
+
+key_table! {
+    "alt" => Alt,
+    "altgr" => AltGr,
+    "backspace" | "back" => Backspace,
+    "capslock" | "caps" => CapsLock,
+    "controlleft" | "ctrl" => ControlLeft,
+    "controlright" => ControlRight,
+    "delete" | "del" => Delete,
+    "downarrow" | "arrowdown" => DownArrow,
+    "end" => End,
+    "escape" | "esc" => Escape,
+    "f1" => F1,
+    "f2" => F2,
+    "f3" => F3,
+    "f4" => F4,
+    "f5" => F5,
+    "f6" => F6,
+    "f7" => F7,
+    "f8" => F8,
+    "f9" => F9,
+    "f10" => F10,
+    "f11" => F11,
+    "f12" => F12,
+    // 没有专门名字的按键以虚拟键码表示
+    "f13" => Unknown(0x7C),
+    "f14" => Unknown(0x7D),
+    "f15" => Unknown(0x7E),
+    "f16" => Unknown(0x7F),
+    "f17" => Unknown(0x80),
+    "f18" => Unknown(0x81),
+    "f19" => Unknown(0x82),
+    "f20" => Unknown(0x83),
+    "f21" => Unknown(0x84),
+    "f22" => Unknown(0x85),
+    "f23" => Unknown(0x86),
+    "f24" => Unknown(0x87),
+    "home" => Home,
+    "leftarrow" | "arrowleft" => LeftArrow,
+    "metaleft" | "meta" | "win" => MetaLeft,
+    "pagedown" => PageDown,
+    "pageup" => PageUp,
+    "return" | "enter" => Return,
+    "rightarrow" | "arrowright" => RightArrow,
+    "shiftleft" | "shift" => ShiftLeft,
+    "shiftright" => ShiftRight,
+    "space" => Space,
+    "tab" => Tab,
+    "uparrow" | "arrowup" => UpArrow,
+    "printscreen" | "prtsc" => PrintScreen,
+    "scrolllock" => ScrollLock,
+    "pause" => Pause,
+    "numlock" => NumLock,
+    "`" | "~" | "backquote" => BackQuote,
+    "1" | "!" | "num1" => Num1,
+    "2" | "@" | "num2" => Num2,
+    "3" | "#" | "num3" => Num3,
+    "4" | "$" | "num4" => Num4,
+    "5" | "%" | "num5" => Num5,
+    "6" | "^" | "num6" => Num6,
+    "7" | "&" | "num7" => Num7,
+    "8" | "num8" => Num8,
+    "9" | "(" | "num9" => Num9,
+    "0" | ")" | "num0" => Num0,
+    "-" | "_" | "minus" => Minus,
+    "=" | "+" | "equal" | "plus" => Equal,
+    "q" | "keyq" => KeyQ,
+    "w" | "keyw" => KeyW,
+    "e" | "keye" => KeyE,
+    "r" | "keyr" => KeyR,
+    "t" | "keyt" => KeyT,
+    "y" | "keyy" => KeyY,
+    "u" | "keyu" => KeyU,
+    "i" | "keyi" => KeyI,
+    "o" | "keyo" => KeyO,
+    "p" | "keyp" => KeyP,
+    "[" | "{" | "leftbracket" => LeftBracket,
+    "]" | "}" | "rightbracket" => RightBracket,
+    "a" | "keya" => KeyA,
+    "s" | "keys" => KeyS,
+    "d" | "keyd" => KeyD,
+    "f" | "keyf" => KeyF,
+    "g" | "keyg" => KeyG,
+    "h" | "keyh" => KeyH,
+    "j" | "keyj" => KeyJ,
+    "k" | "keyk" => KeyK,
+    "l" | "keyl" => KeyL,
+    ";" | ":" | "semicolon" => SemiColon,
+    "'" | "\"" | "quote" => Quote,
+    "\\" | "|" | "backslash" => BackSlash,
+    "intlbackslash" => IntlBackslash,
+    "z" | "keyz" => KeyZ,
+    "x" | "keyx" => KeyX,
+    "c" | "keyc" => KeyC,
+    "v" | "keyv" => KeyV,
+    "b" | "keyb" => KeyB,
+    "n" | "keyn" => KeyN,
+    "m" | "keym" => KeyM,
+    "," | "<" | "comma" => Comma,
+    "." | ">" | "dot" => Dot,
+    "/" | "?" | "slash" => Slash,
+    "insert" | "ins" => Insert,
+    //KP_RETURN, 13,
+    "kpminus" => KpMinus,
+    "kpplus" => KpPlus,
+    "kpmultiply" => KpMultiply,
+    "kpdivide" => KpDivide,
+    "kp0" => Kp0,
+    "kp1" => Kp1,
+    "kp2" => Kp2,
+    "kp3" => Kp3,
+    "kp4" => Kp4,
+    "kp5" => Kp5,
+    "kp6" => Kp6,
+    "kp7" => Kp7,
+    "kp8" => Kp8,
+    "kp9" => Kp9,
+    "kpdelete" => KpDelete,
+    "metaright" => MetaRight,
+    "kpreturn" => KpReturn,
+    "function" => Function,
+    "apps" | "menu" | "contextmenu" => Unknown(0x5D),
+    "sleep" => Unknown(0x5F),
+    "browserback" => Unknown(0xA6),
+    "browserforward" => Unknown(0xA7),
+    "browserrefresh" => Unknown(0xA8),
+    "browserstop" => Unknown(0xA9),
+    "browsersearch" => Unknown(0xAA),
+    "browserfavorites" => Unknown(0xAB),
+    "browserhome" => Unknown(0xAC),
+    "volumemute" | "mute" => Unknown(0xAD),
+    "volumedown" => Unknown(0xAE),
+    "volumeup" => Unknown(0xAF),
+    "medianexttrack" | "medianext" | "nexttrack" => Unknown(0xB0),
+    "mediaprevtrack" | "mediaprev" | "prevtrack" => Unknown(0xB1),
+    "mediastop" => Unknown(0xB2),
+    "mediaplaypause" | "playpause" => Unknown(0xB3),
+    "launchmail" | "mail" => Unknown(0xB4),
+    "launchmediaselect" | "mediaselect" => Unknown(0xB5),
+    "launchapp1" | "app1" => Unknown(0xB6),
+    "launchapp2" | "app2" => Unknown(0xB7),
+}
@@ -0,0 +1,390 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use serde::{de::{DeserializeOwned, Error}, Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use crate::units::string::did_you_mean;
+
+
+/// 区分功能的字段
+pub static FEATURE_TAG: &str = "feature";
+
+/// `capslock_shortcut`中的单个快捷键与功能无关的字段，功能及其字段另外解析为`FeatureModel`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BindingModel {
+    /// 单个按键组合或按键序列
+    pub key: StringList,
+    /// 只在大写锁定开启或关闭时触发
+    #[serde(default)]
+    pub caps_state: Option<CapsState>,
+    /// 连按次数
+    #[serde(default)]
+    pub taps: Option<u32>,
+}
+
+/// 字段表由`feature_names`、`feature_fields`从该模型读取，新增功能或字段时不需要另外登记
+#[derive(Debug, Deserialize)]
+#[serde(tag = "feature", rename_all = "snake_case", deny_unknown_fields)]
+pub enum FeatureModel {
+    InputText {
+        text: StringList,
+    },
+    Input {
+        action: StringList,
+        #[serde(default)]
+        delay: u64,
+    },
+    Multifunctional {},
+    CapsToggle {},
+    Run {
+        program: String,
+        #[serde(default)]
+        args: Option<StringList>,
+        #[serde(default)]
+        cwd: Option<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+        #[serde(default)]
+        shell: bool,
+        #[serde(default)]
+        wait: bool,
+    },
+    Filter {
+        program: String,
+        #[serde(default)]
+        args: Option<StringList>,
+        #[serde(default)]
+        cwd: Option<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+        #[serde(default)]
+        shell: bool,
+        #[serde(default)]
+        timeout: Option<u64>,
+        #[serde(default)]
+        max_size: Option<usize>,
+        #[serde(default)]
+        paste: bool,
+    },
+    Transform {
+        transform: StringList,
+        #[serde(default)]
+        paste: bool,
+    },
+    Format {
+        #[serde(default)]
+        syntax: FormatSyntax,
+        #[serde(default)]
+        style: FormatStyle,
+        #[serde(default)]
+        indent: Option<usize>,
+        #[serde(default)]
+        paste: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapsState {
+    On,
+    Off,
+}
+
+/// `format`按什么语法解析选中的文本
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FormatSyntax {
+    #[default]
+    Json,
+    Yaml,
+    /// 先按JSON解析，失败时再按YAML解析
+    Auto,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FormatStyle {
+    #[default]
+    Pretty,
+    Minify,
+}
+
+/// `BindingModel`的字段
+pub fn binding_fields() -> &'static [&'static str] {
+    static FIELDS: OnceLock<&'static [&'static str]> = OnceLock::new();
+    FIELDS.get_or_init(|| probe::expected::<BindingModel>(&[(probe::UNKNOWN, "")]))
+}
+
+/// 快捷键共有的字段：`BindingModel`的字段与`feature`
+pub fn common_fields() -> impl Iterator<Item = &'static str> {
+    binding_fields().iter().copied().chain([FEATURE_TAG])
+}
+
+/// 全部功能名与各自的字段
+fn feature_table() -> &'static [(&'static str, &'static [&'static str])] {
+    static TABLE: OnceLock<Vec<(&'static str, &'static [&'static str])>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        probe::expected::<FeatureModel>(&[(FEATURE_TAG, probe::UNKNOWN)]).iter()
+            .map(|feature| (*feature, probe::expected::<FeatureModel>(&[(FEATURE_TAG, *feature), (probe::UNKNOWN, "")])))
+            .collect()
+    })
+}
+
+pub fn feature_names() -> impl Iterator<Item = &'static str> {
+    feature_table().iter().map(|(feature, _)| *feature)
+}
+
+pub fn feature_fields(feature: &str) -> Option<&'static [&'static str]> {
+    feature_table().iter().find(|(name, _)| *name == feature).map(|(_, fields)| *fields)
+}
+
+/// 该功能必选的字段，按模型中的顺序排列
+/// 
+/// 缺少字段时serde只报告第一个必选的字段，补上能解析的占位值后继续解析，直到不再缺少字段
+pub fn required_fields(feature: &str) -> Vec<&'static str> {
+    let mut entry = Map::new();
+    entry.insert(FEATURE_TAG.to_string(), Value::from(feature));
+
+    let mut required = vec![];
+    while let Err(Some(field)) = probe::missing_field::<FeatureModel>(&entry) {
+        if required.contains(&field) {
+            break
+        }
+        required.push(field);
+
+        let parsed = probe::placeholders().into_iter().any(|placeholder| {
+            entry.insert(field.to_string(), placeholder);
+            probe::missing_field::<FeatureModel>(&entry) != Err(None)
+        });
+        if !parsed {
+            break
+        }
+    }
+    required
+}
+
+/// 找出该功能用不到的字段，附带最接近的可用字段
+pub fn unknown_fields(entry: &Map<String, Value>, feature: &str) -> Vec<(String, Option<&'static str>)> {
+    let fields = feature_fields(feature).unwrap_or_default();
+    let known = || common_fields().chain(fields.iter().copied());
+
+    entry.keys()
+        .filter(|field| !known().any(|known| known == field.as_str()))
+        .map(|field| (field.clone(), did_you_mean(field, known())))
+        .collect()
+}
+
+/// 找出解析失败的字段
+/// 
+/// serde的类型错误不带字段名。逐个去掉字段后重新解析，去掉后解析成功、或只缺少该字段时，该字段就是出错的字段
+pub fn failing_field<T: DeserializeOwned>(entry: &Map<String, Value>) -> Option<String> {
+    entry.keys()
+        .filter(|field| field.as_str() != FEATURE_TAG)
+        .find(|field| {
+            let mut entry = entry.clone();
+            entry.remove(field.as_str());
+            match probe::missing_field::<T>(&entry) {
+                Ok(()) => true,
+                Err(missing) => missing == Some(field.as_str()),
+            }
+        })
+        .cloned()
+}
+
+/// 从serde生成的代码中读取字段名与变体名
+/// 
+/// 解析未知的字段或变体时，serde会把全部可用的名称交给`de::Error::unknown_field`、`unknown_variant`，
+/// 缺少字段时交给`de::Error::missing_field`，用自定义的错误类型记录下来
+mod probe {
+    use serde::de::{self, value::MapDeserializer, Deserialize, Deserializer, IntoDeserializer, Visitor};
+    use serde_json::{Map, Value};
+
+    /// 不会与任何字段名、功能名重复的名称
+    pub const UNKNOWN: &str = "\0";
+
+    #[derive(Debug)]
+    struct Expected(Option<&'static [&'static str]>);
+
+    impl std::fmt::Display for Expected {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+
+    impl std::error::Error for Expected {}
+
+    impl de::Error for Expected {
+        fn custom<T: std::fmt::Display>(_msg: T) -> Self {
+            Self(None)
+        }
+
+        fn unknown_variant(_variant: &str, expected: &'static [&'static str]) -> Self {
+            Self(Some(expected))
+        }
+
+        fn unknown_field(_field: &str, expected: &'static [&'static str]) -> Self {
+            Self(Some(expected))
+        }
+    }
+
+    /// 按`entry_list`解析，返回serde报告未知名称时给出的全部可用名称
+    pub fn expected<'de, T: Deserialize<'de>>(entry_list: &[(&'de str, &'de str)]) -> &'static [&'static str] {
+        let deserializer = MapDeserializer::<_, Expected>::new(entry_list.iter().copied());
+        match T::deserialize(deserializer) {
+            Err(Expected(Some(expected))) => expected,
+            _ => &[],
+        }
+    }
+
+    /// 记录缺少的字段，其他错误不区分
+    #[derive(Debug)]
+    pub struct Missing(Option<&'static str>);
+
+    impl std::fmt::Display for Missing {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+
+    impl std::error::Error for Missing {}
+
+    impl de::Error for Missing {
+        fn custom<T: std::fmt::Display>(_msg: T) -> Self {
+            Self(None)
+        }
+
+        fn missing_field(field: &'static str) -> Self {
+            Self(Some(field))
+        }
+    }
+
+    /// 以`Missing`为错误类型的JSON值，字段的值本身出错时一律为`Missing(None)`
+    struct ProbeValue<'a>(&'a Value);
+
+    impl<'de> IntoDeserializer<'de, Missing> for ProbeValue<'de> {
+        type Deserializer = Self;
+
+        fn into_deserializer(self) -> Self {
+            self
+        }
+    }
+
+    impl<'de> Deserializer<'de> for ProbeValue<'de> {
+        type Error = Missing;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Missing> {
+            self.0.deserialize_any(visitor).map_err(|_| Missing(None))
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Missing> {
+            self.0.deserialize_option(visitor).map_err(|_| Missing(None))
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value, Missing> {
+            self.0.deserialize_newtype_struct(name, visitor).map_err(|_| Missing(None))
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(self, name: &'static str, variants: &'static [&'static str], visitor: V) -> Result<V::Value, Missing> {
+            self.0.deserialize_enum(name, variants, visitor).map_err(|_| Missing(None))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+        }
+    }
+
+    /// 探查必选字段时依次尝试的字段值
+    pub fn placeholders() -> [Value; 4] {
+        [Value::from("x"), Value::from(0), Value::from(true), Value::Object(Map::new())]
+    }
+
+    /// 按`entry`解析，失败且原因是缺少字段时给出该字段
+    pub fn missing_field<'de, T: Deserialize<'de>>(entry: &'de Map<String, Value>) -> Result<(), Option<&'static str>> {
+        let entry_list = entry.iter().map(|(field, value)| (field.as_str(), ProbeValue(value)));
+        let deserializer = MapDeserializer::<_, Missing>::new(entry_list);
+        T::deserialize(deserializer).map(|_| ()).map_err(|Missing(field)| field)
+    }
+}
+
+/// 单个字符串或字符串列表，数字与布尔值按原样转换为字符串
+#[derive(Debug)]
+pub struct StringList(pub Vec<String>);
+
+impl<'de> Deserialize<'de> for StringList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        fn to_string(value: &Value) -> Option<String> {
+            match value {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                Value::Bool(b) => Some(b.to_string()),
+                _ => None,
+            }
+        }
+
+        let value = Value::deserialize(deserializer)?;
+        let list = match &value {
+            Value::Array(array) => array.iter().map(to_string).collect::<Option<Vec<_>>>(),
+            value => to_string(value).map(|s| vec![s]),
+        };
+
+        match list {
+            Some(list) if !list.is_empty() => Ok(Self(list)),
+            _ => Err(D::Error::custom("expected a string or a non-empty list of strings")),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn field_tables_from_model() {
+        assert_eq!(binding_fields(), ["key", "caps_state", "taps"]);
+        assert_eq!(feature_names().collect::<Vec<_>>(), ["input_text", "input", "multifunctional", "caps_toggle", "run", "filter", "transform", "format"]);
+        assert_eq!(feature_fields("input"), Some(&["action", "delay"][..]));
+        assert_eq!(feature_fields("multifunctional"), Some(&[][..]));
+        assert_eq!(feature_fields("filter"), Some(&["program", "args", "cwd", "env", "shell", "timeout", "max_size", "paste"][..]));
+        assert_eq!(feature_fields("unknown"), None);
+    }
+
+    #[test]
+    fn required_fields_from_model() {
+        assert_eq!(required_fields("input_text"), ["text"]);
+        assert_eq!(required_fields("input"), ["action"]);
+        assert_eq!(required_fields("filter"), ["program"]);
+        assert_eq!(required_fields("format"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn unknown_fields_with_suggestion() {
+        let entry = json!({"key": "Caps + W", "feature": "input", "action": "Up", "dealy": 10, "text": "x"});
+        let mut unknown = unknown_fields(entry.as_object().unwrap(), "input");
+        unknown.sort();
+        assert_eq!(unknown, [("dealy".to_string(), Some("delay")), ("text".to_string(), None)]);
+    }
+
+    #[test]
+    fn failing_field_of_type_error() {
+        let entry = json!({"feature": "input", "action": "Up", "delay": "slow"});
+        assert_eq!(failing_field::<FeatureModel>(entry.as_object().unwrap()).as_deref(), Some("delay"));
+        let entry = json!({"feature": "input", "action": {}, "delay": 10});
+        assert_eq!(failing_field::<FeatureModel>(entry.as_object().unwrap()).as_deref(), Some("action"));
+        let entry = json!({"key": "Caps + W", "taps": -1});
+        assert_eq!(failing_field::<BindingModel>(entry.as_object().unwrap()).as_deref(), Some("taps"));
+    }
+
+    #[test]
+    fn missing_field_from_serde() {
+        let entry = json!({"feature": "input", "delay": 10});
+        assert_eq!(probe::missing_field::<FeatureModel>(entry.as_object().unwrap()), Err(Some("action")));
+        let entry = json!({"feature": "input", "action": "Up", "delay": "slow"});
+        assert_eq!(probe::missing_field::<FeatureModel>(entry.as_object().unwrap()), Err(None));
+        let entry = json!({"key": "Caps + W"});
+        assert_eq!(probe::missing_field::<BindingModel>(entry.as_object().unwrap()), Ok(()));
+    }
+}
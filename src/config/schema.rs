@@ -0,0 +1,339 @@
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+
+use crate::{feature::transform::transform_names, i18n::LANGUAGE_NAMES, units::file_io};
+
+use super::{key_from_str::{display_key_name, KEY_NAMES, RAW_CODE_PREFIXES}, model::{self, FEATURE_TAG}, modifier_names, CHAR_KEY_PREFIX};
+
+
+/// 默认导出的文件名，与配置文件放在同一文件夹
+pub static SCHEMA_FILE_NAME: &str = "capslock_forget_config.schema.json";
+
+/// 把配置文件的JSON Schema写入`path`
+pub fn export(path: &Path) -> std::io::Result<()> {
+    let text = serde_json::to_string_pretty(&config_schema()).map_err(std::io::Error::other)?;
+    file_io::write(path, text)
+}
+
+/// 整个配置文件的JSON Schema
+///
+/// 语言、功能与按键名都取自解析配置时使用的同一份列表，不会与解析器不一致
+pub fn config_schema() -> Value {
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Capslock Forge configuration",
+        "type": "object",
+        "definitions": {
+            "key": key_schema(),
+            "sequence": sequence_schema(),
+        },
+        "properties": {
+            "language": {
+                "description": "Interface language. Any value starting with 'en' is English, unknown values follow the system language.",
+                "anyOf": [
+                    { "enum": LANGUAGE_NAMES },
+                    { "type": "string", "pattern": "^[eE][nN]" },
+                ],
+            },
+            "strict_load": {
+                "description": "Disable all shortcuts when any shortcut has an error.",
+                "type": "boolean",
+                "default": false,
+            },
+            "auto_reload": {
+                "description": "Reload when a file in the configuration folder or a file listed in include changes.",
+                "type": "boolean",
+                "default": true,
+            },
+            "layout_aware_keys": {
+                "description": "Resolve single-character key names to the key that types the character in the current keyboard layout.",
+                "type": "boolean",
+                "default": false,
+            },
+            "sequence_timeout": {
+                "description": "Longest pause between the steps of a key sequence, in milliseconds.",
+                "type": "integer",
+                "minimum": 0,
+                "default": 1000,
+            },
+            "sequence_replay": {
+                "description": "Send the keys of an unfinished key sequence as normal keys.",
+                "type": "boolean",
+                "default": false,
+            },
+            "tap_interval": {
+                "description": "Longest pause between the presses of a multi-tap shortcut, in milliseconds.",
+                "type": "integer",
+                "minimum": 0,
+                "default": 300,
+            },
+            "caps_tap": {
+                "description": "Feature used when Capslock is tapped alone, written like a shortcut without 'key'.",
+                "allOf": [binding_schema(false)],
+            },
+            "caps_tapping_term": {
+                "description": "Capslock held longer than this, in milliseconds, is not a tap.",
+                "type": "integer",
+                "minimum": 0,
+                "default": 200,
+            },
+            "include": {
+                "description": "Extra configuration files loaded before this one, relative to the configuration folder.",
+                "anyOf": [
+                    { "type": "string" },
+                    { "type": "array", "items": { "type": "string" } },
+                ],
+            },
+            "duplicate_binding": report_level_schema("How to report shortcuts with the same or a shadowed trigger."),
+            "unknown_field": report_level_schema("How to report fields the feature does not use."),
+            "capslock_shortcut": {
+                "description": "Shortcuts triggered while Capslock is held.",
+                "anyOf": [
+                    { "type": "array", "items": binding_schema(true) },
+                    { "type": "string", "description": "Legacy form: a JSON array of shortcuts." },
+                ],
+            },
+        },
+    })
+}
+
+fn report_level_schema(description: &str) -> Value {
+    json!({
+        "description": description,
+        "enum": ["ignore", "warning", "error"],
+        "default": "warning",
+    })
+}
+
+/// 单个快捷键，按`feature`的取值分别限定可用的字段
+/// 
+/// `is_binding`为`false`时用于`caps_tap`等没有`key`、`caps_state`的功能
+fn binding_schema(is_binding: bool) -> Value {
+    let feature_list = model::feature_names()
+        .map(|feature| {
+            let fields = model::feature_fields(feature).unwrap_or_default();
+            let mut properties = Map::new();
+            for field in model::common_fields().filter(|field| is_binding || *field == FEATURE_TAG) {
+                properties.insert(field.to_string(), json!(true));
+            }
+            for field in fields.iter() {
+                properties.insert(field.to_string(), field_schema(field).unwrap_or(json!(true)));
+            }
+            let required = model::required_fields(feature);
+
+            json!({
+                "if": { "properties": { "feature": { "const": feature } } },
+                "then": {
+                    "properties": properties,
+                    "required": required,
+                    "additionalProperties": false,
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut schema = json!({
+        "type": "object",
+        "required": ["feature"],
+        "properties": {
+            "feature": {
+                "enum": model::feature_names().collect::<Vec<_>>(),
+            },
+        },
+        "allOf": feature_list,
+    });
+
+    if is_binding {
+        schema["required"] = json!(["key", "feature"]);
+        schema["properties"]["key"] = json!({
+            "$ref": "#/definitions/sequence",
+            "description": "Key pressed together with Capslock, e.g. 'Quote' or 'Ctrl + K', or a sequence such as 'Caps + W, Caps + S', where steps without 'Caps' are pressed after releasing Capslock, e.g. 'Caps + G, H'.",
+        });
+        schema["properties"]["caps_state"] = json!({
+            "description": "Only trigger while Caps Lock is on or off.",
+            "enum": ["on", "off"],
+        });
+        schema["properties"]["taps"] = json!({
+            "description": "Number of presses within 'tap_interval' that trigger the shortcut, e.g. 2 for a double tap.",
+            "type": "integer",
+            "minimum": 1,
+            "default": 1,
+        });
+    }
+
+    schema
+}
+
+/// 功能字段的Schema，必选的字段由`model::required_fields`给出
+fn field_schema(field: &str) -> Option<Value> {
+    let schema = match field {
+        "text" => string_list_schema(json!({ "type": ["string", "number", "boolean"] }), "Text to type, a list is typed in turn."),
+        "action" => string_list_schema(json!({ "$ref": "#/definitions/key" }), "Keys to send, in order."),
+        "delay" => json!({
+            "description": "Delay between keys in milliseconds.",
+            "type": "integer",
+            "minimum": 0,
+            "default": 0,
+        }),
+        "program" => json!({
+            "description": "Program to start, or the command to run when 'shell' is true. '{config_dir}' and '{config_file}' are replaced with the configuration paths.",
+            "type": "string",
+            "minLength": 1,
+        }),
+        "args" => string_list_schema(json!({ "type": ["string", "number", "boolean"] }), "Arguments passed to the program."),
+        "cwd" => json!({
+            "description": "Working directory, relative to the configuration folder.",
+            "type": "string",
+        }),
+        "env" => json!({
+            "description": "Environment variables added to the program's environment.",
+            "type": "object",
+            "additionalProperties": { "type": "string" },
+        }),
+        "shell" => json!({
+            "description": "Run 'program' as a command with 'cmd /C'.",
+            "type": "boolean",
+            "default": false,
+        }),
+        "wait" => json!({
+            "description": "Wait for the program to exit and log its exit status. The shortcut does not start it again while it is running.",
+            "type": "boolean",
+            "default": false,
+        }),
+        "timeout" => json!({
+            "description": "Milliseconds to wait for the command before it is stopped.",
+            "type": "integer",
+            "minimum": 0,
+            "default": 5000,
+        }),
+        "max_size" => json!({
+            "description": "Largest selection and command output, in bytes.",
+            "type": "integer",
+            "minimum": 0,
+            "default": 1048576,
+        }),
+        "transform" => string_list_schema(
+            json!({ "enum": transform_names().collect::<Vec<_>>() }),
+            "Transform applied to the selection, a list is used in turn.",
+        ),
+        "syntax" => json!({
+            "description": "How the selection is parsed. 'auto' tries JSON first, then YAML.",
+            "enum": ["json", "yaml", "auto"],
+            "default": "json",
+        }),
+        "style" => json!({
+            "description": "Pretty-print or minify the selection.",
+            "enum": ["pretty", "minify"],
+            "default": "pretty",
+        }),
+        "indent" => json!({
+            "description": "Spaces per indentation level when pretty-printing JSON.",
+            "type": "integer",
+            "minimum": 0,
+            "default": 2,
+        }),
+        "paste" => json!({
+            "description": "Paste the result through the clipboard instead of typing it.",
+            "type": "boolean",
+            "default": false,
+        }),
+        _ => return None,
+    };
+    Some(schema)
+}
+
+fn string_list_schema(item: Value, description: &str) -> Value {
+    json!({
+        "description": description,
+        "anyOf": [
+            item,
+            { "type": "array", "items": item, "minItems": 1 },
+        ],
+    })
+}
+
+/// 按键组合，例如`Ctrl + K`
+///
+/// 列出全部按键名用于补全，按键名不区分大小写，校验改用正则表达式
+fn key_schema() -> Value {
+    let names = KEY_NAMES.iter().map(|name| display_key_name(name)).collect::<Vec<_>>();
+
+    json!({
+        "anyOf": [
+            { "enum": names },
+            { "type": "string", "pattern": format!("^{}$", chord_pattern()) },
+        ],
+    })
+}
+
+/// 触发按键，单个按键组合、以`,`分隔的按键序列，或每项一步的列表
+fn sequence_schema() -> Value {
+    let chord = chord_pattern();
+    json!({
+        "anyOf": [
+            { "$ref": "#/definitions/key" },
+            { "type": "string", "pattern": format!("^{chord}(,{chord})*$") },
+            { "type": "array", "items": { "$ref": "#/definitions/key" }, "minItems": 1 },
+        ],
+    })
+}
+
+fn chord_pattern() -> String {
+    let raw_code_list = RAW_CODE_PREFIXES.iter()
+        .map(|prefix| format!("{}(0[xX][0-9a-fA-F]+|[0-9]+)", case_insensitive_pattern(prefix)));
+    let token = modifier_names().chain(KEY_NAMES.iter().map(|name| name.to_string()))
+        .map(|name| case_insensitive_pattern(&name))
+        .chain(raw_code_list)
+        .chain(std::iter::once(format!("{}.", case_insensitive_pattern(CHAR_KEY_PREFIX))))
+        .collect::<Vec<_>>()
+        .join("|");
+
+    format!(r"\s*({token})(\s*\+\s*({token}))*\s*")
+}
+
+/// 转换为不区分大小写的正则表达式，JSON Schema的正则表达式不支持`(?i)`
+///
+/// 只转义语法字符，编辑器以Unicode模式解析时其他字符不能转义
+fn case_insensitive_pattern(name: &str) -> String {
+    let mut pattern = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphabetic() {
+            pattern.push('[');
+            pattern.push(c.to_ascii_lowercase());
+            pattern.push(c.to_ascii_uppercase());
+            pattern.push(']');
+        } else if "^$\\.*+?()[]{}|/".contains(c) {
+            pattern.push('\\');
+            pattern.push(c);
+        } else {
+            pattern.push(c);
+        }
+    }
+    pattern
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_feature_field_has_schema() {
+        for feature in model::feature_names() {
+            for field in model::feature_fields(feature).unwrap_or_default() {
+                assert!(field_schema(field).is_some(), "field '{field}' of feature '{feature}' has no schema");
+            }
+        }
+    }
+
+    #[test]
+    fn required_fields_follow_model() {
+        let schema = binding_schema(true);
+        let feature_list = schema["allOf"].as_array().unwrap();
+        for (feature, feature_schema) in model::feature_names().zip(feature_list) {
+            assert_eq!(feature_schema["if"]["properties"]["feature"]["const"], feature);
+            assert_eq!(feature_schema["then"]["required"], json!(model::required_fields(feature)));
+        }
+    }
+}
@@ -1,11 +1,21 @@
-use std::{collections::HashMap, sync::atomic::AtomicBool};
+use std::{collections::HashMap, path::PathBuf, process::Command, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, time::Duration};
 
 use caps_shortcut::Key;
 use enigo::Keyboard;
 use rdev::EventType;
 use yaml_rust2::ScanError;
 
-use crate::config::{self, parse_shortcut_key_text, replace_text_config, CapslockForgetFeature, ModifierKey, ShortcutKeyConfigFileFormatError};
+use crate::{config::{self, parse_shortcut_key_text, replace_text_config, CapslockForgetFeature, ModifierKey, ReportLevel, Settings, ShortcutKeyConfig, ShortcutKeyConfigFileFormatError}, units::{clipboard, keyboard}};
+
+mod encoding;
+
+mod leader;
+
+pub mod filter;
+
+pub mod format;
+
+pub mod transform;
 
 
 static CAPS_SHORTCUT_LISTENER_LOCK: AtomicBool = AtomicBool::new(false);
@@ -19,6 +29,79 @@ fn set_caps_shortcut_listener_lock(val: bool) {
 }
 
 
+type ErrorReporter = Box<dyn Fn(String) + Send>;
+
+static ERROR_REPORTER: Mutex<Option<ErrorReporter>> = Mutex::new(None);
+
+/// 设置功能运行时出错的提示方式，托盘启动时设置
+pub fn set_error_reporter<F: Fn(String) + Send + 'static>(reporter: F) {
+    *ERROR_REPORTER.lock().unwrap() = Some(Box::new(reporter));
+}
+
+/// 提示功能运行时的错误，例如命令执行失败
+fn report_error(text: String) {
+    println!("Feature Error: {}", text);
+    if let Some(reporter) = ERROR_REPORTER.lock().unwrap().as_ref() {
+        reporter(text);
+    }
+}
+
+/// 读取选中的文本，在后台线程中转换后替换选中的文本
+/// 
+/// 没有选中文本时不做改动；转换出错时保留选中的文本并提示错误。
+/// `paste`为`true`时通过剪贴板粘贴，否则模拟输入
+fn replace_selection<F: FnOnce(String) -> Result<String, String> + Send + 'static>(paste: bool, transform: F) {
+    caps_shortcut::freeze_listener();
+    let text = selection::get_text();
+    caps_shortcut::unfreeze_listener();
+
+    if text.is_empty() {
+        return
+    }
+
+    std::thread::spawn(move || {
+        match transform(text) {
+            Ok(text) if paste => paste_text(&text),
+            Ok(text) => {
+                if let Ok(mut enigo) = enigo::Enigo::new(&enigo::Settings::default()) {
+                    let _ = enigo.text(text.as_str());
+                }
+            },
+            Err(err) => report_error(err),
+        }
+    });
+}
+
+/// 通过剪贴板粘贴文本，之后恢复剪贴板原有的文本
+fn paste_text(text: &str) {
+    let previous = clipboard::get_text();
+    if !clipboard::set_text(text) {
+        report_error("Could not write to the clipboard".to_string());
+        return
+    }
+
+    set_caps_shortcut_listener_lock(true);
+    caps_shortcut::freeze_listener();
+    let modifier_list = [Key::ControlLeft].into_iter().filter(|key| !keyboard::is_key_down(*key)).collect::<Vec<_>>();
+    let event_list = modifier_list.iter().map(|key| EventType::KeyPress(*key))
+        .chain([EventType::KeyPress(Key::KeyV), EventType::KeyRelease(Key::KeyV)])
+        .chain(modifier_list.iter().map(|key| EventType::KeyRelease(*key)));
+    for event in event_list {
+        if let Err(err) = rdev::simulate(&event) {
+            println!("We could not send {:?}", err);
+        }
+    }
+    caps_shortcut::unfreeze_listener();
+    set_caps_shortcut_listener_lock(false);
+
+    // 等待目标程序读取剪贴板后再恢复
+    std::thread::sleep(Duration::from_millis(200));
+    if let Some(previous) = previous {
+        clipboard::set_text(&previous);
+    }
+}
+
+
 #[derive(Debug)]
 pub struct MultifunctionalAction;
 
@@ -39,6 +122,124 @@ impl MultifunctionalAction {
 }
 
 
+/// 切换系统的大写锁定状态
+#[derive(Debug)]
+pub struct CapsToggleAction;
+
+impl CapsToggleAction {
+    /// 模拟按下`Capslock`
+    /// 
+    /// 模拟期间加锁并冻结`caps_shortcut`，模拟的按键不会被当作快捷键或轻按`Capslock`
+    fn execute(&self) {
+        set_caps_shortcut_listener_lock(true);
+        std::thread::spawn(|| {
+            caps_shortcut::freeze_listener();
+            for event in [EventType::KeyPress(Key::CapsLock), EventType::KeyRelease(Key::CapsLock)] {
+                if let Err(err) = rdev::simulate(&event) {
+                    println!("We could not send {:?}", err);
+                }
+            }
+            caps_shortcut::unfreeze_listener();
+            set_caps_shortcut_listener_lock(false);
+        });
+    }
+}
+
+
+/// 要执行的程序与参数
+#[derive(Debug)]
+pub struct CommandLine {
+    program: String,
+    args: Vec<String>,
+    cwd: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    /// 通过`cmd /C`执行，`program`为命令
+    shell: bool,
+}
+
+impl CommandLine {
+    pub fn new(program: String, args: Vec<String>, cwd: Option<PathBuf>, env: Vec<(String, String)>, shell: bool) -> Self {
+        Self {
+            program,
+            args,
+            cwd,
+            env,
+            shell,
+        }
+    }
+
+    fn command(&self) -> Command {
+        let mut command = if self.shell {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            // cmd不认识`\"`形式的转义，命令原样传入，例如`"C:\Program Files\x.exe" arg`
+            #[cfg(windows)]
+            std::os::windows::process::CommandExt::raw_arg(&mut command, &self.program);
+            #[cfg(not(windows))]
+            command.arg(&self.program);
+            command
+        } else {
+            Command::new(&self.program)
+        };
+        command.args(&self.args).envs(self.env.iter().map(|(key, value)| (key, value)));
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        command
+    }
+}
+
+/// 启动程序或执行命令
+#[derive(Debug)]
+pub struct RunAction {
+    command_line: CommandLine,
+    /// 等待退出并记录退出状态
+    wait: bool,
+    /// 等待退出时，程序是否仍在运行
+    running: Arc<AtomicBool>,
+}
+
+impl RunAction {
+    pub fn new(command_line: CommandLine, wait: bool) -> Self {
+        Self {
+            command_line,
+            wait,
+            running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// 默认启动后不再理会；`wait`为`true`时在后台等待退出，运行期间再次触发不会重复启动
+    fn execute(&self) {
+        if self.wait && self.running.load(Ordering::Relaxed) {
+            println!("Run '{}': still running", self.command_line.program);
+            return
+        }
+
+        let mut child = match self.command_line.command().spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                report_error(format!("Run '{}': {}", self.command_line.program, err));
+                return
+            },
+        };
+
+        if self.wait {
+            let program = self.command_line.program.clone();
+            let running = self.running.clone();
+            running.store(true, Ordering::Relaxed);
+            std::thread::spawn(move || {
+                match child.wait() {
+                    Ok(status) if status.success() => println!("Run '{}': {}", program, status),
+                    Ok(status) => report_error(format!("Run '{}': {}", program, status)),
+                    Err(err) => report_error(format!("Run '{}': {}", program, err)),
+                }
+                running.store(false, Ordering::Relaxed);
+            });
+        }
+    }
+}
+
+
 #[derive(Debug, Clone, Copy)]
 pub struct InputKey {
     pub key: Key,
@@ -88,31 +289,14 @@ impl InputKeyAction {
             }
 
             for input_key in input_key_list {
-                let InputKey {key, modifier_key: ModifierKey {ctrl, shift, alt, meta }, delay} = input_key;
-                let ctrl_event_press = EventType::KeyPress(Key::ControlLeft);
-                let shift_event_press = EventType::KeyPress(Key::ShiftLeft);
-                let alt_event_press = EventType::KeyPress(Key::Alt);
-                let meta_event_press = EventType::KeyPress(Key::MetaLeft);
-
-                let ctrl_event_release = EventType::KeyRelease(Key::ControlLeft);
-                let shift_event_release = EventType::KeyRelease(Key::ShiftLeft);
-                let alt_event_release = EventType::KeyRelease(Key::Alt);
-                let meta_event_release = EventType::KeyRelease(Key::MetaLeft);
-
-                if ctrl {
-                    send_key_event(&ctrl_event_press);
-                }
-
-                if shift {
-                    send_key_event(&shift_event_press);
-                }
-
-                if alt {
-                    send_key_event(&alt_event_press);
-                }
-
-                if meta {
-                    send_key_event(&meta_event_press);
+                let InputKey {key, modifier_key, delay} = input_key;
+                // 已经按下的修饰键不再按下与松开，以免打断正在按住的修饰键
+                let modifier_list = modifier_key.keys().into_iter()
+                    .filter(|modifier| !keyboard::is_key_down(*modifier))
+                    .collect::<Vec<_>>();
+
+                for modifier in modifier_list.iter() {
+                    send_key_event(&EventType::KeyPress(*modifier));
                 }
                 
                 let key_press_event = EventType::KeyPress(key);
@@ -121,23 +305,10 @@ impl InputKeyAction {
                 send_key_event(&key_press_event);
                 send_key_event(&key_release_event);
 
-                if ctrl {
-                    send_key_event(&ctrl_event_release);
-                }
-
-                if shift {
-                    send_key_event(&shift_event_release);
+                for modifier in modifier_list.iter() {
+                    send_key_event(&EventType::KeyRelease(*modifier));
                 }
 
-                if alt {
-                    send_key_event(&alt_event_release);
-                }
-
-                if meta {
-                    send_key_event(&meta_event_release);
-                }
-                
-
                 std::thread::sleep(std::time::Duration::from_millis(delay));
             }
             set_caps_shortcut_listener_lock(false);
@@ -177,84 +348,503 @@ impl InputTextAction {
 
 #[derive(Debug)]
 pub enum LoadError {
-    ScanError(ScanError),
+    IoError(PathBuf, std::io::Error),
+    ScanError(PathBuf, ScanError),
     FileNotFound,
-    JsonError(serde_json::Error),
     ConfigError(ShortcutKeyConfigFileFormatError),
-    ReplaceTextConfigError(ini::Error),
+    ConflictError(Vec<ShortcutKeyConfigFileFormatError>),
+    ReplaceTextConfigError(PathBuf, ini::Error),
 }
 
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::IoError(path, err) => write!(f, "{}: {}", path.display(), err),
+            LoadError::ScanError(path, err) => write!(f, "{}: invalid YAML: {}", path.display(), err),
+            LoadError::FileNotFound => write!(f, "{}: 'capslock_shortcut' not found", config::capslock_forget_config_file_path().display()),
+            LoadError::ConfigError(err) => write!(f, "{}", err),
+            LoadError::ConflictError(err_list) => {
+                let text_list = err_list.iter().map(|err| err.to_string()).collect::<Vec<_>>();
+                write!(f, "{}", text_list.join("\n"))
+            },
+            LoadError::ReplaceTextConfigError(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+
+/// 已校验的快捷键表
+/// 
+/// 加载配置时先完整构建，应用之前不影响正在运行的快捷键
+pub struct BindingTable {
+    list: Vec<ShortcutKeyConfig>,
+    replace_text_map: HashMap<String, String>,
+    sequence_timeout: Duration,
+    sequence_replay: bool,
+    tap_interval: Duration,
+    caps_tap: Option<CapslockForgetFeature>,
+    caps_tapping_term: Duration,
+}
 
-pub fn reload() -> Result<(), LoadError> {
+/// 最近一次加载时读取的配置文件
+static LOADED_CONFIG_FILES: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+
+/// 最近一次加载时读取的配置文件，包括`include`引入的配置文件夹外的文件
+pub fn loaded_config_files() -> Vec<PathBuf> {
+    LOADED_CONFIG_FILES.lock().unwrap().clone()
+}
+
+/// 读取并校验配置，不改动正在运行的快捷键
+/// 
+/// 非严格加载时跳过有误的快捷键，成功时一并返回被跳过的快捷键的错误
+pub fn load() -> Result<(BindingTable, Vec<ShortcutKeyConfigFileFormatError>), LoadError> {
+    let settings = Settings::load();
+    let strict_load = settings.strict_load;
+    config::update_layout_aware_keys(&settings);
     let mut list = vec![];
-    if let Ok(item) = config::shortcut_key_config() {
-        match item {
-            Ok(item) => {
-                match item {
-                    Some(item) => {
-                        match item {
-                            Ok(item) => {
-                                for c in item {
-                                    match c {
-                                        Ok(config) => {
-                                            list.push(config);
-                                        },
-                                        Err(err) => {
-                                            return Err(LoadError::ConfigError(err))
-                                        },
+    let mut warnings = vec![];
+    // 被其他文件覆盖的快捷键，与同一文件中的重复一起按`duplicate_binding`处理
+    let mut override_list = vec![];
+    let main_config_file_path = config::capslock_forget_config_file_path();
+
+    // 非严格加载时，`include`与`conf.d`中出错的文件只提示并跳过，主配置文件出错时仍然无法加载
+    let skip_file_error = |path: &PathBuf| !strict_load && *path != main_config_file_path;
+
+    let config_file_list = config::shortcut_key_config_files(&settings);
+    // 加载失败时也更新，修正出错的文件后能自动重新加载
+    *LOADED_CONFIG_FILES.lock().unwrap() = config_file_list.clone();
+
+    for path in config_file_list {
+        match config::shortcut_key_config(&path, &settings) {
+            Ok(item) => match item {
+                Ok(item) => {
+                    match item {
+                        Some(item) => {
+                            match item {
+                                Ok(item) => {
+                                    warnings.extend(item.warnings);
+                                    // 后加载的文件覆盖先加载的文件中触发按键相同的快捷键
+                                    for c in item.list {
+                                        match c {
+                                            Ok(config) => {
+                                                if let Some(earlier) = list.iter().find(|c: &&ShortcutKeyConfig| c.source.file != path && c.same_trigger(&config)) {
+                                                    override_list.push(config::binding_override(earlier, &config));
+                                                }
+                                                list.retain(|c: &ShortcutKeyConfig| c.source.file == path || !c.same_trigger(&config));
+                                                list.push(config);
+                                            },
+                                            Err(err) => {
+                                                if strict_load {
+                                                    return Err(LoadError::ConfigError(err))
+                                                }
+                                                warnings.push(err);
+                                            },
+                                        }
                                     }
-                                }
-                            },
-                            Err(err) => return Err(LoadError::JsonError(err)),
-                        }
-                    },
-                    None => return Err(LoadError::FileNotFound),
-                }
+                                },
+                                Err(err) => {
+                                    if !skip_file_error(&path) {
+                                        return Err(LoadError::ConfigError(err))
+                                    }
+                                    warnings.push(err);
+                                },
+                            }
+                        },
+                        None => {
+                            if path == main_config_file_path {
+                                return Err(LoadError::FileNotFound)
+                            }
+                        },
+                    }
+                },
+                Err(err) => {
+                    if !skip_file_error(&path) {
+                        return Err(LoadError::ScanError(path, err));
+                    }
+                    warnings.push(ShortcutKeyConfigFileFormatError::scan_error(&path, &err));
+                },
             },
             Err(err) => {
-                return Err(LoadError::ScanError(err));
+                if !skip_file_error(&path) {
+                    return Err(LoadError::IoError(path, err))
+                }
+                warnings.push(ShortcutKeyConfigFileFormatError::io_error(&path, &err));
             },
         }
     }
 
-    let map = match replace_text_config() {
+    match settings.duplicate_binding {
+        ReportLevel::Ignore => (),
+        ReportLevel::Warning => warnings.extend(override_list.into_iter().chain(config::binding_conflicts(&list))),
+        ReportLevel::Error => {
+            let conflict_list = override_list.into_iter().chain(config::binding_conflicts(&list)).collect::<Vec<_>>();
+            if !conflict_list.is_empty() {
+                return Err(LoadError::ConflictError(conflict_list));
+            }
+        },
+    }
+
+    let replace_text_map = match replace_text_config() {
         Ok(map) => map,
-        Err(err) => return Err(LoadError::ReplaceTextConfigError(err)),
+        Err(err) => return Err(LoadError::ReplaceTextConfigError(config::replace_text_file_path(), err)),
     };
 
+    let caps_tap = match settings.caps_tap() {
+        Some(Ok(feature)) => Some(feature),
+        Some(Err(err)) => {
+            if strict_load {
+                return Err(LoadError::ConfigError(err))
+            }
+            warnings.push(err);
+            None
+        },
+        None => None,
+    };
+
+    let table = BindingTable {
+        list,
+        replace_text_map,
+        sequence_timeout: Duration::from_millis(settings.sequence_timeout),
+        sequence_replay: settings.sequence_replay,
+        tap_interval: Duration::from_millis(settings.tap_interval),
+        caps_tap,
+        caps_tapping_term: Duration::from_millis(settings.caps_tapping_term),
+    };
+
+    Ok((table, warnings))
+}
+
+/// 监听中的快捷键表，以及序列快捷键与连按的进度
+struct ListenerState {
+    list: Vec<ShortcutKeyConfig>,
+    map: HashMap<String, String>,
+    sequence_timeout: Duration,
+    sequence_replay: bool,
+    /// 每次开始或推进序列、连按时加一，用于判断超时是否已经过期
+    generation: u64,
+    pending: Option<PendingSequence>,
+    tap: Option<PendingTap>,
+}
+
+impl ListenerState {
+    fn execute(&mut self, index: usize) {
+        execute(&mut self.list[index].feature, &self.map);
+    }
+}
+
+struct PendingSequence {
+    /// 仍可能完成的快捷键在表中的序号
+    candidate_list: Vec<usize>,
+    /// 已经按下的后续按键数
+    step: usize,
+    /// 已经吞掉的按键，序列未完成时用于重放
+    swallowed: Vec<InputKey>,
+}
+
+/// 等待下一次按下的连按
+struct PendingTap {
+    /// 按下的按键与修饰键
+    key: Key,
+    modifier_key: ModifierKey,
+    /// 已经按下的次数
+    taps: u32,
+    /// 不再按下时使用的快捷键在表中的序号
+    index: Option<usize>,
+}
+
+/// 用新的快捷键表替换正在运行的快捷键
+pub fn apply(table: BindingTable) {
+    let BindingTable { list, replace_text_map: map, sequence_timeout, sequence_replay, tap_interval, caps_tap, caps_tapping_term } = table;
+
+    // 本次按住`Capslock`期间是否按下过其他按键
+    let caps_layer_used = Arc::new(AtomicBool::new(false));
+
     caps_shortcut::clear_all_listener();
+    leader::stop();
+    listen_caps_tap(caps_tap, caps_tapping_term, &map, &caps_layer_used);
+
+    let state = Arc::new(Mutex::new(ListenerState { list, map, sequence_timeout, sequence_replay, generation: 0, pending: None, tap: None }));
     caps_shortcut::caps_listener_with(move |keyboard| {
-        if !caps_shortcut_listener_is_lock() {
-            for config in list.iter_mut() {
-                if config.key == keyboard.key && config.modifier_key.match_key(keyboard.ctrl, keyboard.shift, keyboard.alt, keyboard.meta) {
-                    match &mut config.feature {
-                        CapslockForgetFeature::InputText(input_text_action) => {
-                            input_text_action.input_next_text();
-                        },
-                        CapslockForgetFeature::Input(input_key_action) => {
-                            input_key_action.execute();
-                        },
-                        CapslockForgetFeature::Multifunctional(multifunctional_action) => {
-                            multifunctional_action.replace_text(&map);
-                        },
-                    }
-    
-                    return true
+        caps_layer_used.store(true, Ordering::Relaxed);
+
+        if caps_shortcut_listener_is_lock() {
+            return false
+        }
+
+        let is_pressed = |key: Key, modifier_key: &ModifierKey| {
+            key == keyboard.key && modifier_key.match_key(keyboard.ctrl, keyboard.shift, keyboard.alt, keyboard.meta)
+        };
+        let input_key = InputKey::with(keyboard.key, ModifierKey::pressed(keyboard.ctrl, keyboard.shift, keyboard.alt, keyboard.meta));
+
+        let mut state_guard = state.lock().unwrap();
+
+        // 序列未完成时当前按键照常处理
+        if advance_sequence(&state, &mut state_guard, input_key, true, is_pressed) {
+            return true
+        }
+
+        // 连按期间按下其他按键时，立即使用已按下的次数对应的快捷键
+        let mut taps = 1;
+        if let Some(tap) = state_guard.tap.take() {
+            if tap.key == input_key.key && tap.modifier_key == input_key.modifier_key {
+                taps = tap.taps + 1;
+            } else if let Some(index) = tap.index {
+                state_guard.execute(index);
+            }
+        }
+
+        let is_triggered = |config: &ShortcutKeyConfig| is_pressed(config.key, &config.modifier_key) && config.caps_state_matches();
+
+        let Some(index) = state_guard.list.iter().position(is_triggered) else {
+            return false
+        };
+
+        if state_guard.list[index].sequence.is_empty() {
+            // 该按键有连按快捷键时，等到不再按下或达到最多的连按次数再使用
+            let tap_list = state_guard.list.iter().enumerate()
+                .filter(|(_, config)| config.sequence.is_empty() && is_triggered(config))
+                .map(|(index, config)| (index, config.taps))
+                .collect::<Vec<_>>();
+            let max_taps = tap_list.iter().map(|(_, taps)| *taps).max().unwrap_or(1);
+            let index = tap_list.iter().find(|(_, config_taps)| *config_taps == taps).map(|(index, _)| *index);
+
+            if taps >= max_taps {
+                if let Some(index) = index {
+                    state_guard.execute(index);
                 }
+            } else {
+                let tap = PendingTap { key: input_key.key, modifier_key: input_key.modifier_key, taps, index };
+                start_tap_timeout(&state, &mut state_guard, tap, tap_interval);
             }
+        } else {
+            // 以该按键开头的序列快捷键都可能完成，排在后面的单个按键的快捷键被遮蔽
+            let candidate_list = (index..state_guard.list.len())
+                .filter(|index| !state_guard.list[*index].sequence.is_empty() && is_triggered(&state_guard.list[*index]))
+                .collect();
+            let pending = PendingSequence { candidate_list, step: 0, swallowed: vec![input_key] };
+            start_sequence_timeout(&state, &mut state_guard, pending, true);
         }
-        false
+
+        true
     });
-    
-    Ok(())
+}
+
+fn execute(feature: &mut CapslockForgetFeature, map: &HashMap<String, String>) {
+    match feature {
+        CapslockForgetFeature::InputText(input_text_action) => {
+            input_text_action.input_next_text();
+        },
+        CapslockForgetFeature::Input(input_key_action) => {
+            input_key_action.execute();
+        },
+        CapslockForgetFeature::Multifunctional(multifunctional_action) => {
+            multifunctional_action.replace_text(map);
+        },
+        CapslockForgetFeature::CapsToggle(caps_toggle_action) => {
+            caps_toggle_action.execute();
+        },
+        CapslockForgetFeature::Run(run_action) => {
+            run_action.execute();
+        },
+        CapslockForgetFeature::Filter(filter_action) => {
+            filter_action.execute();
+        },
+        CapslockForgetFeature::Transform(transform_action) => {
+            transform_action.execute();
+        },
+        CapslockForgetFeature::Format(format_action) => {
+            format_action.execute();
+        },
+    }
+}
+
+/// 监听单独轻按`Capslock`
+/// 
+/// `Capslock`本身的按下与松开由`caps_shortcut::caps_key_listener_with`报告，只在启用`caps-tap`特性时可用
+#[cfg(feature = "caps-tap")]
+fn listen_caps_tap(feature: Option<CapslockForgetFeature>, tapping_term: Duration, map: &HashMap<String, String>, caps_layer_used: &Arc<AtomicBool>) {
+    let Some(feature) = feature else { return };
+    let mut caps_tap = CapsTap {
+        feature,
+        tapping_term,
+        map: map.clone(),
+        pressed_at: None,
+        caps_layer_used: caps_layer_used.clone(),
+    };
+    caps_shortcut::caps_key_listener_with(move |is_pressed| caps_tap.on_caps_key(is_pressed));
+}
+
+/// 未启用`caps-tap`特性时`Settings::caps_tap`报告错误，不会有轻按的功能
+#[cfg(not(feature = "caps-tap"))]
+fn listen_caps_tap(_feature: Option<CapslockForgetFeature>, _tapping_term: Duration, _map: &HashMap<String, String>, _caps_layer_used: &Arc<AtomicBool>) {}
+
+/// 单独轻按`Capslock`时的功能
+/// 
+/// `caps_shortcut`报告`Capslock`本身的按下与松开，期间没有按下其他按键、且在`tapping_term`内松开时视为轻按。
+/// 状态只在`caps_shortcut`的回调中使用，不经过全局的锁
+#[cfg(feature = "caps-tap")]
+struct CapsTap {
+    feature: CapslockForgetFeature,
+    tapping_term: Duration,
+    map: HashMap<String, String>,
+    pressed_at: Option<std::time::Instant>,
+    caps_layer_used: Arc<AtomicBool>,
+}
+
+#[cfg(feature = "caps-tap")]
+impl CapsTap {
+    fn on_caps_key(&mut self, is_pressed: bool) {
+        // 模拟输入期间（例如`caps_toggle`模拟的`Capslock`）不算轻按
+        if caps_shortcut_listener_is_lock() {
+            self.pressed_at = None;
+            return
+        }
+
+        if is_pressed {
+            // 按住时的自动重复不重新计时
+            if self.pressed_at.is_none() {
+                self.pressed_at = Some(std::time::Instant::now());
+                self.caps_layer_used.store(false, Ordering::Relaxed);
+            }
+            return
+        }
+
+        let Some(pressed_at) = self.pressed_at.take() else { return };
+        if !self.caps_layer_used.load(Ordering::Relaxed) && pressed_at.elapsed() <= self.tapping_term {
+            execute(&mut self.feature, &self.map);
+        }
+    }
+}
+
+/// 用按下的按键推进正在进行的序列，返回该按键是否属于序列、需要吞掉
+/// 
+/// `hold_caps`为按下时是否按住`Capslock`。没有序列能继续时放弃序列，按`sequence_replay`重放已吞掉的按键
+fn advance_sequence<F: Fn(Key, &ModifierKey) -> bool>(state: &Arc<Mutex<ListenerState>>, state_guard: &mut ListenerState, input_key: InputKey, hold_caps: bool, is_pressed: F) -> bool {
+    let Some(mut pending) = state_guard.pending.take() else {
+        return false
+    };
+
+    let step = pending.step;
+    let list = &state_guard.list;
+    pending.candidate_list.retain(|index| {
+        list[*index].sequence.get(step).is_some_and(|step| step.hold_caps == hold_caps && is_pressed(step.key, &step.modifier_key))
+    });
+
+    if pending.candidate_list.is_empty() {
+        leader::stop();
+        if state_guard.sequence_replay {
+            InputKeyAction::new(pending.swallowed).execute();
+        }
+        return false
+    }
+
+    pending.step += 1;
+    let completed = pending.candidate_list.iter().copied().find(|index| list[*index].sequence.len() == pending.step);
+    match completed {
+        Some(index) => {
+            leader::stop();
+            state_guard.execute(index);
+        },
+        None => {
+            pending.swallowed.push(input_key);
+            start_sequence_timeout(state, state_guard, pending, hold_caps);
+        },
+    }
+    true
+}
+
+/// 记录序列进度，超时后放弃该序列
+/// 
+/// 下一步需要松开`Capslock`按下时安装临时的键盘钩子，`caps_held`为此时`Capslock`是否按住
+fn start_sequence_timeout(state: &Arc<Mutex<ListenerState>>, state_guard: &mut ListenerState, pending: PendingSequence, caps_held: bool) {
+    let list = &state_guard.list;
+    let wait_leader = pending.candidate_list.iter()
+        .any(|index| list[*index].sequence.get(pending.step).is_some_and(|step| !step.hold_caps));
+    if wait_leader {
+        let leader_state = state.clone();
+        leader::start(caps_held, move |key| {
+            let input_key = InputKey::with(key, modifier_key_pressed());
+            let is_pressed = |step_key: Key, modifier_key: &ModifierKey| {
+                step_key == key && modifier_key.match_key(is_ctrl_down(), is_shift_down(), is_alt_down(), is_meta_down())
+            };
+            let mut state_guard = leader_state.lock().unwrap();
+            advance_sequence(&leader_state, &mut state_guard, input_key, false, is_pressed)
+        });
+    } else {
+        leader::stop();
+    }
+
+    state_guard.generation += 1;
+    state_guard.pending = Some(pending);
+
+    let generation = state_guard.generation;
+    let timeout = state_guard.sequence_timeout;
+    let state = state.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(timeout);
+        let mut state = state.lock().unwrap();
+        if state.generation != generation {
+            return
+        }
+        if let Some(pending) = state.pending.take() {
+            leader::stop();
+            if state.sequence_replay {
+                InputKeyAction::new(pending.swallowed).execute();
+            }
+        }
+    });
+}
+
+fn is_ctrl_down() -> bool {
+    keyboard::is_key_down(Key::ControlLeft) || keyboard::is_key_down(Key::ControlRight)
+}
+
+fn is_shift_down() -> bool {
+    keyboard::is_key_down(Key::ShiftLeft) || keyboard::is_key_down(Key::ShiftRight)
+}
+
+fn is_alt_down() -> bool {
+    keyboard::is_key_down(Key::Alt) || keyboard::is_key_down(Key::AltGr)
+}
+
+fn is_meta_down() -> bool {
+    keyboard::is_key_down(Key::MetaLeft) || keyboard::is_key_down(Key::MetaRight)
+}
+
+/// 当前按下的修饰键，不区分左右
+fn modifier_key_pressed() -> ModifierKey {
+    ModifierKey::pressed(is_ctrl_down(), is_shift_down(), is_alt_down(), is_meta_down())
+}
+
+/// 记录连按进度，超时后使用已按下的次数对应的快捷键
+fn start_tap_timeout(state: &Arc<Mutex<ListenerState>>, state_guard: &mut ListenerState, tap: PendingTap, interval: Duration) {
+    state_guard.generation += 1;
+    state_guard.tap = Some(tap);
+
+    let generation = state_guard.generation;
+    let state = state.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(interval);
+        let mut state = state.lock().unwrap();
+        if state.generation != generation {
+            return
+        }
+        if let Some(PendingTap { index: Some(index), .. }) = state.tap.take() {
+            state.execute(index);
+        }
+    });
+}
+
+/// 重新加载配置，失败时保留正在运行的快捷键
+pub fn reload() -> Result<Vec<ShortcutKeyConfigFileFormatError>, LoadError> {
+    let (table, warnings) = load()?;
+    apply(table);
+    Ok(warnings)
 }
 
 pub fn run() {
     caps_shortcut::run();
 }
 
-pub fn clear() {
-    caps_shortcut::clear_all_listener();
-}
 
 
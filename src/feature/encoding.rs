@@ -0,0 +1,329 @@
+static BASE64_TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64_encode(text: &str) -> String {
+    let mut result = String::new();
+    for chunk in text.as_bytes().chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, byte)| bits | (*byte as u32) << (16 - index * 8));
+        for index in 0..4 {
+            if index <= chunk.len() {
+                result.push(BASE64_TABLE[(bits >> (18 - index * 6)) as usize & 0x3F] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+/// 同时接受标准与URL安全的字母表，忽略空白，末尾的`=`可以省略
+pub fn base64_decode(text: &str) -> Result<String, String> {
+    let mut bytes = vec![];
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+
+    for c in text.chars().filter(|c| !c.is_whitespace()).collect::<String>().trim_end_matches('=').chars() {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' | '-' => 62,
+            '/' | '_' => 63,
+            _ => return Err(format!("invalid Base64 character '{c}'")),
+        };
+        bits = bits << 6 | value;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+
+    if bit_count >= 6 {
+        return Err("invalid Base64 length".to_string())
+    }
+    utf8_text(bytes)
+}
+
+/// 百分号编码，只保留RFC 3986中不需要编码的字符
+pub fn url_encode(text: &str) -> String {
+    let mut result = String::new();
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            result.push(byte as char);
+        } else {
+            result.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    result
+}
+
+/// `+`保持原样，不视为空格
+pub fn url_decode(text: &str) -> Result<String, String> {
+    let mut bytes = vec![];
+    let mut iter = text.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next(), iter.next()];
+            match hex {
+                [Some(high), Some(low)] => bytes.push(hex_byte(high, low).ok_or_else(|| format!("invalid escape '%{}{}'", high as char, low as char))?),
+                _ => return Err("incomplete escape at the end".to_string()),
+            }
+        } else {
+            bytes.push(byte);
+        }
+    }
+    utf8_text(bytes)
+}
+
+pub fn html_encode(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// 解码常用的命名实体与数字实体，无法识别的实体保持原样
+pub fn html_decode(text: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest.find(';').map(|end| &rest[1..end]).filter(|entity| entity.len() <= 10);
+        let decoded = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{A0}'),
+            _ => {
+                let code = match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|decimal| decimal.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+            },
+        });
+
+        match (entity, decoded) {
+            (Some(entity), Some(c)) => {
+                result.push(c);
+                rest = &rest[entity.len() + 2..];
+            },
+            _ => {
+                result.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// 转义为JSON字符串的内容，不加首尾的引号
+pub fn json_escape(text: &str) -> String {
+    let quoted = serde_json::to_string(text).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// 带不带首尾的引号都可以
+pub fn json_unescape(text: &str) -> Result<String, String> {
+    let quoted = if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        text.to_string()
+    } else {
+        format!("\"{text}\"")
+    };
+    serde_json::from_str::<String>(&quoted).map_err(|err| format!("invalid JSON string: {err}"))
+}
+
+pub fn hex_encode(text: &str) -> String {
+    text.bytes().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// 忽略空白与`0x`前缀
+pub fn hex_decode(text: &str) -> Result<String, String> {
+    let digits = text.split_whitespace()
+        .map(|part| part.strip_prefix("0x").or_else(|| part.strip_prefix("0X")).unwrap_or(part))
+        .collect::<String>();
+    if digits.len() % 2 != 0 {
+        return Err("odd number of hex digits".to_string())
+    }
+
+    let bytes = digits.as_bytes()
+        .chunks(2)
+        .map(|pair| hex_byte(pair[0], pair[1]).ok_or_else(|| format!("invalid hex digits '{}'", String::from_utf8_lossy(pair))))
+        .collect::<Result<Vec<_>, _>>()?;
+    utf8_text(bytes)
+}
+
+/// 非ASCII字符转义为`\uXXXX`，超出基本平面的字符使用代理对
+pub fn unicode_escape(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        if c.is_ascii() {
+            result.push(c);
+        } else {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                result.push_str(&format!("\\u{:04x}", unit));
+            }
+        }
+    }
+    result
+}
+
+/// 解码`\uXXXX`（含代理对）与`\u{X}`，其余文本保持原样
+///
+/// 无法识别的转义与不成对的代理也保持原样，例如路径`C:\users`
+pub fn unicode_unescape(text: &str) -> String {
+    // 每个`\uXXXX`的码元与原文
+    let mut units: Vec<(u16, &str)> = vec![];
+    let mut result = String::new();
+    let mut rest = text;
+
+    fn flush(units: &mut Vec<(u16, &str)>, result: &mut String) {
+        let mut iter = units.drain(..).peekable();
+        while let Some((unit, original)) = iter.next() {
+            let low = iter.next_if(|(low, _)| (0xD800..0xDC00).contains(&unit) && (0xDC00..0xE000).contains(low));
+            let decoded = match low {
+                Some((low, _)) => char::decode_utf16([unit, low]).next().and_then(Result::ok),
+                None => char::from_u32(unit as u32),
+            };
+            match decoded {
+                Some(c) => result.push(c),
+                None => result.push_str(original),
+            }
+        }
+    }
+
+    while let Some(start) = rest.find("\\u") {
+        // 连续的`\uXXXX`之间没有其他文本时才可能组成代理对
+        if start > 0 {
+            flush(&mut units, &mut result);
+            result.push_str(&rest[..start]);
+        }
+
+        let escape = &rest[start + 2..];
+        let braced = escape.strip_prefix('{')
+            .and_then(|braced| braced.find('}').map(|end| (&braced[..end], &braced[end + 1..])))
+            .and_then(|(hex, after)| u32::from_str_radix(hex, 16).ok().and_then(char::from_u32).map(|c| (c, after)));
+        if let Some((c, after)) = braced {
+            flush(&mut units, &mut result);
+            result.push(c);
+            rest = after;
+            continue
+        }
+
+        match escape.get(..4).filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit())) {
+            Some(hex) => {
+                units.push((u16::from_str_radix(hex, 16).unwrap(), &rest[start..start + 6]));
+                rest = &escape[4..];
+            },
+            None => {
+                flush(&mut units, &mut result);
+                result.push_str("\\u");
+                rest = escape;
+            },
+        }
+    }
+
+    flush(&mut units, &mut result);
+    result.push_str(rest);
+    result
+}
+
+fn hex_byte(high: u8, low: u8) -> Option<u8> {
+    let digit = |c: u8| (c as char).to_digit(16);
+    Some((digit(high)? * 16 + digit(low)?) as u8)
+}
+
+/// 解码结果都按UTF-8文本处理
+fn utf8_text(bytes: Vec<u8>) -> Result<String, String> {
+    String::from_utf8(bytes).map_err(|_| "decoded bytes are not UTF-8 text".to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_round_trip() {
+        assert_eq!(base64_encode(""), "");
+        assert_eq!(base64_encode("f"), "Zg==");
+        assert_eq!(base64_encode("fo"), "Zm8=");
+        assert_eq!(base64_encode("foo"), "Zm9v");
+        assert_eq!(base64_encode("中文"), "5Lit5paH");
+        assert_eq!(base64_decode("5Lit5paH").unwrap(), "中文");
+    }
+
+    #[test]
+    fn base64_decode_lenient_input() {
+        // 末尾的`=`可以省略，忽略空白，接受URL安全的字母表
+        assert_eq!(base64_decode("Zg").unwrap(), "f");
+        assert_eq!(base64_decode("Zm9v\r\nYmFy").unwrap(), "foobar");
+        assert_eq!(base64_decode("-_8=").unwrap_err(), "decoded bytes are not UTF-8 text");
+        assert_eq!(base64_decode("Zm9v!").unwrap_err(), "invalid Base64 character '!'");
+        assert_eq!(base64_decode("Z").unwrap_err(), "invalid Base64 length");
+    }
+
+    #[test]
+    fn url_round_trip() {
+        assert_eq!(url_encode("a b&c/中~"), "a%20b%26c%2F%E4%B8%AD~");
+        assert_eq!(url_decode("a%20b%26c%2F%E4%B8%AD~").unwrap(), "a b&c/中~");
+        assert_eq!(url_decode("a+b").unwrap(), "a+b");
+        assert_eq!(url_decode("%zz").unwrap_err(), "invalid escape '%zz'");
+        assert_eq!(url_decode("abc%4").unwrap_err(), "incomplete escape at the end");
+    }
+
+    #[test]
+    fn html_round_trip() {
+        assert_eq!(html_encode("<a href=\"x\">'&'</a>"), "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;");
+        assert_eq!(html_decode("&lt;b&gt; &#65;&#x42;&nbsp;").unwrap(), "<b> AB\u{A0}");
+        // 无法识别的实体与单独的`&`保持原样
+        assert_eq!(html_decode("a & b &unknown; &#xZZ;").unwrap(), "a & b &unknown; &#xZZ;");
+    }
+
+    #[test]
+    fn json_round_trip() {
+        assert_eq!(json_escape("a\"b\\c\n"), "a\\\"b\\\\c\\n");
+        assert_eq!(json_unescape("a\\\"b\\\\c\\n").unwrap(), "a\"b\\c\n");
+        assert_eq!(json_unescape("\"quoted\\t\"").unwrap(), "quoted\t");
+        assert!(json_unescape("bad\\x").is_err());
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(hex_encode("Az中"), "417ae4b8ad");
+        assert_eq!(hex_decode("41 7A 0xe4b8ad").unwrap(), "Az中");
+        assert_eq!(hex_decode("417").unwrap_err(), "odd number of hex digits");
+        assert_eq!(hex_decode("4g").unwrap_err(), "invalid hex digits '4g'");
+    }
+
+    #[test]
+    fn unicode_round_trip() {
+        assert_eq!(unicode_escape("a中😀"), "a\\u4e2d\\ud83d\\ude00");
+        assert_eq!(unicode_unescape("a\\u4e2d\\ud83d\\ude00"), "a中😀");
+        assert_eq!(unicode_unescape("\\u{1F600} \\u4E2D"), "😀 中");
+    }
+
+    #[test]
+    fn unicode_unescape_keeps_unrecognised_escapes() {
+        assert_eq!(unicode_unescape("C:\\users\\name"), "C:\\users\\name");
+        assert_eq!(unicode_unescape("\\u12 \\u{zz} \\u{41"), "\\u12 \\u{zz} \\u{41");
+        // 不成对的代理保持原文
+        assert_eq!(unicode_unescape("\\uD83D!"), "\\uD83D!");
+        assert_eq!(unicode_unescape("\\ude00\\ud83d"), "\\ude00\\ud83d");
+        assert_eq!(unicode_unescape("\\ud83d\\u0041"), "\\ud83dA");
+    }
+}
@@ -0,0 +1,110 @@
+use std::{io::{Read, Write}, process::Stdio, sync::Arc, time::{Duration, Instant}};
+
+use super::{replace_selection, CommandLine};
+
+
+/// 用于过滤命令的进程不显示控制台窗口
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+/// 把选中的文本交给命令的标准输入，用命令的输出替换选中的文本
+#[derive(Debug)]
+pub struct FilterAction {
+    command_line: Arc<CommandLine>,
+    timeout: Duration,
+    /// 选中的文本与命令输出的最大字节数
+    max_size: usize,
+    paste: bool,
+}
+
+impl FilterAction {
+    pub fn new(command_line: CommandLine, timeout: Duration, max_size: usize, paste: bool) -> Self {
+        Self {
+            command_line: Arc::new(command_line),
+            timeout,
+            max_size,
+            paste,
+        }
+    }
+
+    pub(super) fn execute(&self) {
+        let command_line = self.command_line.clone();
+        let timeout = self.timeout;
+        let max_size = self.max_size;
+        replace_selection(self.paste, move |text| {
+            filter(&command_line, text, timeout, max_size)
+                .map_err(|err| format!("Filter '{}': {}", command_line.program, err))
+        });
+    }
+}
+
+/// 执行命令，超时或输出过大时结束命令
+fn filter(command_line: &CommandLine, text: String, timeout: Duration, max_size: usize) -> Result<String, String> {
+    if text.len() > max_size {
+        return Err(format!("selection is larger than {} bytes", max_size))
+    }
+
+    let mut command = command_line.command();
+    command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    let mut child = command.spawn().map_err(|err| err.to_string())?;
+
+    // 分别在线程中读写，以免输入或输出较多时互相等待
+    let mut stdin = child.stdin.take().unwrap();
+    let ends_with_newline = text.ends_with('\n');
+    std::thread::spawn(move || {
+        let _ = stdin.write_all(text.as_bytes());
+    });
+    // 多读一个字节用于判断是否超过上限，读到上限后关闭管道
+    let stdout = child.stdout.take().unwrap();
+    let stdout_reader = std::thread::spawn(move || {
+        let mut buf = vec![];
+        let _ = stdout.take(max_size as u64 + 1).read_to_end(&mut buf);
+        buf
+    });
+    let stderr = child.stderr.take().unwrap();
+    let stderr_reader = std::thread::spawn(move || {
+        let mut buf = vec![];
+        let _ = stderr.take(4096).read_to_end(&mut buf);
+        buf
+    });
+
+    let started_at = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started_at.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {} ms", timeout.as_millis()))
+            },
+            Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+            Err(err) => return Err(err.to_string()),
+        }
+    };
+
+    let output = stdout_reader.join().unwrap_or_default();
+    if output.len() > max_size {
+        return Err(format!("output is larger than {} bytes", max_size))
+    }
+
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&stderr_reader.join().unwrap_or_default()).to_string();
+        return match stderr.lines().map(str::trim).find(|line| !line.is_empty()) {
+            Some(line) => Err(format!("{}: {}", status, line)),
+            None => Err(status.to_string()),
+        }
+    }
+
+    let output = String::from_utf8_lossy(&output);
+    // 命令通常在末尾加上换行，选中的文本没有换行时去掉
+    let output = match output.strip_suffix('\n') {
+        Some(output) if !ends_with_newline => output.strip_suffix('\r').unwrap_or(output),
+        _ => &output,
+    };
+    Ok(output.to_string())
+}
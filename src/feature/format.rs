@@ -0,0 +1,221 @@
+use yaml_rust2::{Yaml, YamlEmitter, YamlLoader};
+
+use crate::config::model::{FormatStyle, FormatSyntax};
+
+use super::replace_selection;
+
+
+/// 把选中的JSON或YAML替换为格式化或压缩后的文本
+#[derive(Debug)]
+pub struct FormatAction {
+    syntax: FormatSyntax,
+    style: FormatStyle,
+    /// JSON格式化时的缩进空格数
+    indent: usize,
+    paste: bool,
+}
+
+impl FormatAction {
+    pub fn new(syntax: FormatSyntax, style: FormatStyle, indent: usize, paste: bool) -> Self {
+        Self {
+            syntax,
+            style,
+            indent,
+            paste,
+        }
+    }
+
+    pub(super) fn execute(&self) {
+        let (syntax, style, indent) = (self.syntax, self.style, self.indent);
+        replace_selection(self.paste, move |text| {
+            let result = match syntax {
+                FormatSyntax::Json => format_json(&text, style, indent),
+                FormatSyntax::Yaml => format_yaml(&text, style),
+                // 先按JSON解析，失败时再按YAML解析
+                FormatSyntax::Auto => format_json(&text, style, indent)
+                    .or_else(|json_err| format_yaml(&text, style).map_err(|yaml_err| format!("{json_err}; {yaml_err}"))),
+            };
+
+            // 选中的文本末尾有换行时保留
+            result.map(|formatted| if text.ends_with('\n') { formatted + "\n" } else { formatted })
+                .map_err(|err| format!("Format: {err}"))
+        });
+    }
+}
+
+/// 先用`serde_json`校验，再逐个字符调整字符串以外的空白
+///
+/// 不经过`serde_json::Value`，键的顺序与数字的写法保持不变
+fn format_json(text: &str, style: FormatStyle, indent: usize) -> Result<String, String> {
+    serde_json::from_str::<serde::de::IgnoredAny>(text).map_err(|err| format!("invalid JSON: {err}"))?;
+
+    let mut result = String::with_capacity(text.len());
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = text.trim().chars().peekable();
+
+    let newline = |result: &mut String, depth: usize| {
+        result.push('\n');
+        result.push_str(&" ".repeat(depth * indent));
+    };
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            result.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue
+        }
+
+        match c {
+            c if c.is_whitespace() => (),
+            '"' => {
+                in_string = true;
+                result.push(c);
+            },
+            _ if style == FormatStyle::Minify => result.push(c),
+            '{' | '[' => {
+                result.push(c);
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                // 空的对象与数组写在同一行
+                if chars.peek().is_some_and(|next| *next == '}' || *next == ']') {
+                    result.push(chars.next().unwrap());
+                } else {
+                    depth += 1;
+                    newline(&mut result, depth);
+                }
+            },
+            '}' | ']' => {
+                depth -= 1;
+                newline(&mut result, depth);
+                result.push(c);
+            },
+            ',' => {
+                result.push(c);
+                newline(&mut result, depth);
+            },
+            ':' => result.push_str(": "),
+            c => result.push(c),
+        }
+    }
+
+    Ok(result)
+}
+
+/// 格式化时使用块样式；压缩时使用单行的流样式，与JSON写法相同
+///
+/// YAML中的注释不会保留
+///
+/// 任意文本都能解析为YAML的标量，所以每个文档都必须是映射或序列，否则视为不是YAML
+fn format_yaml(text: &str, style: FormatStyle) -> Result<String, String> {
+    let doc_list = YamlLoader::load_from_str(text).map_err(|err| format!("invalid YAML: {err}"))?;
+    if doc_list.is_empty() {
+        return Err("invalid YAML: no document".to_string())
+    }
+    if !doc_list.iter().all(|doc| matches!(doc, Yaml::Hash(_) | Yaml::Array(_))) {
+        return Err("invalid YAML: not a mapping or sequence".to_string())
+    }
+
+    let mut text_list = vec![];
+    for doc in doc_list.iter() {
+        let mut text = String::new();
+        match style {
+            FormatStyle::Pretty => {
+                YamlEmitter::new(&mut text).dump(doc).map_err(|err| format!("{err:?}"))?;
+                text = text.strip_prefix("---").unwrap_or(&text).trim_start_matches('\n').to_string();
+            },
+            FormatStyle::Minify => yaml_flow(doc, &mut text),
+        }
+        text_list.push(text);
+    }
+
+    Ok(text_list.join("\n---\n"))
+}
+
+fn yaml_flow(yaml: &Yaml, text: &mut String) {
+    match yaml {
+        Yaml::Real(real) => text.push_str(real),
+        Yaml::Integer(integer) => text.push_str(&integer.to_string()),
+        Yaml::String(string) => text.push_str(&serde_json::to_string(string).unwrap_or_default()),
+        Yaml::Boolean(boolean) => text.push_str(&boolean.to_string()),
+        Yaml::Array(array) => {
+            text.push('[');
+            for (index, item) in array.iter().enumerate() {
+                if index > 0 {
+                    text.push(',');
+                }
+                yaml_flow(item, text);
+            }
+            text.push(']');
+        },
+        Yaml::Hash(hash) => {
+            text.push('{');
+            for (index, (key, value)) in hash.iter().enumerate() {
+                if index > 0 {
+                    text.push(',');
+                }
+                yaml_flow(key, text);
+                text.push(':');
+                yaml_flow(value, text);
+            }
+            text.push('}');
+        },
+        Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => text.push_str("null"),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_pretty() {
+        let text = r#"{"b":1,"a":[1, 2.50,{}],"s":"x: {,}\" y","e":[ ]}"#;
+        assert_eq!(format_json(text, FormatStyle::Pretty, 2).unwrap(), [
+            "{",
+            "  \"b\": 1,",
+            "  \"a\": [",
+            "    1,",
+            "    2.50,",
+            "    {}",
+            "  ],",
+            "  \"s\": \"x: {,}\\\" y\",",
+            "  \"e\": []",
+            "}",
+        ].join("\n"));
+        assert_eq!(format_json("[1,[2]]", FormatStyle::Pretty, 4).unwrap(), "[\n    1,\n    [\n        2\n    ]\n]");
+    }
+
+    #[test]
+    fn json_minify() {
+        let text = "{\n  \"a b\": [ 1, 2 ],\n  \"c\": \"d e\"\n}\n";
+        assert_eq!(format_json(text, FormatStyle::Minify, 2).unwrap(), r#"{"a b":[1,2],"c":"d e"}"#);
+    }
+
+    #[test]
+    fn json_invalid() {
+        assert!(format_json("{\"a\":}", FormatStyle::Pretty, 2).unwrap_err().starts_with("invalid JSON"));
+        assert!(format_json("plain text", FormatStyle::Minify, 2).is_err());
+    }
+
+    #[test]
+    fn yaml_pretty_and_minify() {
+        let text = "a: {b: [1, x]}\nc: true";
+        assert_eq!(format_yaml(text, FormatStyle::Pretty).unwrap(), "a:\n  b:\n    - 1\n    - x\nc: true");
+        assert_eq!(format_yaml(text, FormatStyle::Minify).unwrap(), r#"{"a":{"b":[1,"x"]},"c":true}"#);
+        assert_eq!(format_yaml("- 1\n---\n- 2", FormatStyle::Minify).unwrap(), "[1]\n---\n[2]");
+    }
+
+    #[test]
+    fn yaml_scalar_is_not_structured() {
+        assert!(format_yaml("plain text", FormatStyle::Minify).is_err());
+        assert!(format_yaml("- 1\n---\ntext", FormatStyle::Pretty).is_err());
+        assert!(format_yaml("", FormatStyle::Pretty).is_err());
+    }
+}
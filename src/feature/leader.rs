@@ -0,0 +1,113 @@
+use std::{cell::RefCell, sync::{atomic::{AtomicU64, Ordering}, mpsc, Mutex}};
+
+use caps_shortcut::Key;
+use windows_sys::Win32::{Foundation::{LPARAM, LRESULT, WPARAM}, System::{LibraryLoader::GetModuleHandleW, Threading::GetCurrentThreadId}, UI::{Input::KeyboardAndMouse::*, WindowsAndMessaging::*}};
+
+use crate::units::keyboard;
+
+
+/// 正在运行的钩子所在的线程，以及钩子的编号
+///
+/// 松开`Capslock`后按下的按键`caps_shortcut`监听不到，序列快捷键等待这样的一步时才临时安装键盘钩子，
+/// 序列完成、放弃或超时后立即卸载
+static HOOK: Mutex<Option<(u32, u64)>> = Mutex::new(None);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+struct HookState {
+    id: u64,
+    /// `Capslock`是否按住，按住时的按键交给`caps_shortcut`处理
+    caps_held: bool,
+    on_key: Box<dyn FnMut(Key) -> bool>,
+}
+
+thread_local! {
+    static HOOK_STATE: RefCell<Option<HookState>> = const { RefCell::new(None) };
+}
+
+/// 安装钩子，已安装时不做任何事
+///
+/// 松开`Capslock`后按下按键（不包括修饰键本身与模拟的输入）时调用`on_key`，返回`true`时吞掉该按键。
+/// `caps_held`为安装时`Capslock`是否按住
+pub fn start<F: FnMut(Key) -> bool + Send + 'static>(caps_held: bool, on_key: F) {
+    let mut hook = HOOK.lock().unwrap();
+    if hook.is_some() {
+        return
+    }
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        HOOK_STATE.with(|state| *state.borrow_mut() = Some(HookState { id, caps_held, on_key: Box::new(on_key) }));
+        unsafe {
+            let mut msg = std::mem::zeroed::<MSG>();
+            // 先建立消息队列，`stop`才能向该线程发送`WM_QUIT`
+            PeekMessageW(&mut msg, std::ptr::null_mut(), 0, 0, PM_NOREMOVE);
+            let hhook = SetWindowsHookExW(WH_KEYBOARD_LL, Some(hook_proc), GetModuleHandleW(std::ptr::null()), 0);
+            let _ = tx.send((!hhook.is_null()).then(|| GetCurrentThreadId()));
+            if hhook.is_null() {
+                return
+            }
+
+            while GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) > 0 {}
+            UnhookWindowsHookEx(hhook);
+        }
+    });
+
+    if let Ok(Some(thread_id)) = rx.recv() {
+        *hook = Some((thread_id, id));
+    }
+}
+
+/// 卸载钩子，未安装时不做任何事
+pub fn stop() {
+    if let Some((thread_id, _)) = HOOK.lock().unwrap().take() {
+        unsafe {
+            PostThreadMessageW(thread_id, WM_QUIT, 0, 0);
+        }
+    }
+}
+
+unsafe extern "system" fn hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code == HC_ACTION as i32 && on_event(wparam, &*(lparam as *const KBDLLHOOKSTRUCT)) {
+        return 1
+    }
+    CallNextHookEx(std::ptr::null_mut(), code, wparam, lparam)
+}
+
+/// 处理一个按键事件，返回是否吞掉该事件
+fn on_event(wparam: WPARAM, info: &KBDLLHOOKSTRUCT) -> bool {
+    if info.flags & LLKHF_INJECTED != 0 {
+        return false
+    }
+
+    let is_down = matches!(wparam as u32, WM_KEYDOWN | WM_SYSKEYDOWN);
+    let vk = info.vkCode as VIRTUAL_KEY;
+
+    HOOK_STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let Some(state) = state.as_mut() else { return false };
+
+        if vk == VK_CAPITAL {
+            state.caps_held = is_down;
+            return false
+        }
+
+        // 已经卸载、等待退出的钩子不再处理
+        let is_current = HOOK.lock().unwrap().is_some_and(|(_, id)| id == state.id);
+        if !is_down || state.caps_held || !is_current || is_modifier(vk) {
+            return false
+        }
+
+        (state.on_key)(keyboard::key_from_virtual_key(vk))
+    })
+}
+
+fn is_modifier(vk: VIRTUAL_KEY) -> bool {
+    matches!(
+        vk,
+        VK_SHIFT | VK_LSHIFT | VK_RSHIFT | VK_CONTROL | VK_LCONTROL | VK_RCONTROL
+            | VK_MENU | VK_LMENU | VK_RMENU | VK_LWIN | VK_RWIN
+    )
+}
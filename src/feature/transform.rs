@@ -0,0 +1,281 @@
+use super::{encoding, replace_selection};
+
+
+/// 对选中文本的转换
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    UpperCase,
+    LowerCase,
+    TitleCase,
+    CamelCase,
+    PascalCase,
+    SnakeCase,
+    KebabCase,
+    Trim,
+    SortLines,
+    DedupeLines,
+    ReverseLines,
+    ReverseText,
+    CollapseWhitespace,
+    Base64Encode,
+    Base64Decode,
+    UrlEncode,
+    UrlDecode,
+    HtmlEncode,
+    HtmlDecode,
+    JsonEscape,
+    JsonUnescape,
+    HexEncode,
+    HexDecode,
+    UnicodeEscape,
+    UnicodeUnescape,
+}
+
+/// 配置中的转换名
+pub static TRANSFORM_TABLE: &[(&str, Transform)] = &[
+    ("upper_case", Transform::UpperCase),
+    ("lower_case", Transform::LowerCase),
+    ("title_case", Transform::TitleCase),
+    ("camel_case", Transform::CamelCase),
+    ("pascal_case", Transform::PascalCase),
+    ("snake_case", Transform::SnakeCase),
+    ("kebab_case", Transform::KebabCase),
+    ("trim", Transform::Trim),
+    ("sort_lines", Transform::SortLines),
+    ("dedupe_lines", Transform::DedupeLines),
+    ("reverse_lines", Transform::ReverseLines),
+    ("reverse_text", Transform::ReverseText),
+    ("collapse_whitespace", Transform::CollapseWhitespace),
+    ("base64_encode", Transform::Base64Encode),
+    ("base64_decode", Transform::Base64Decode),
+    ("url_encode", Transform::UrlEncode),
+    ("url_decode", Transform::UrlDecode),
+    ("html_encode", Transform::HtmlEncode),
+    ("html_decode", Transform::HtmlDecode),
+    ("json_escape", Transform::JsonEscape),
+    ("json_unescape", Transform::JsonUnescape),
+    ("hex_encode", Transform::HexEncode),
+    ("hex_decode", Transform::HexDecode),
+    ("unicode_escape", Transform::UnicodeEscape),
+    ("unicode_unescape", Transform::UnicodeUnescape),
+];
+
+pub fn transform_names() -> impl Iterator<Item = &'static str> {
+    TRANSFORM_TABLE.iter().map(|(name, _)| *name)
+}
+
+impl Transform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        TRANSFORM_TABLE.iter().find(|(n, _)| *n == name).map(|(_, transform)| *transform)
+    }
+
+    pub fn name(&self) -> &'static str {
+        TRANSFORM_TABLE.iter().find(|(_, transform)| transform == self).map(|(name, _)| *name).unwrap_or_default()
+    }
+
+    /// 解码的输入有误时返回错误，选中的文本保持不变
+    pub fn apply(&self, text: &str) -> Result<String, String> {
+        let text = match self {
+            Transform::UpperCase => text.to_uppercase(),
+            Transform::LowerCase => text.to_lowercase(),
+            Transform::TitleCase => title_case(text),
+            Transform::CamelCase => map_lines(text, |line| join_words(line, "", |index, word| {
+                if index == 0 { word.to_lowercase() } else { capitalize(word) }
+            })),
+            Transform::PascalCase => map_lines(text, |line| join_words(line, "", |_, word| capitalize(word))),
+            Transform::SnakeCase => map_lines(text, |line| join_words(line, "_", |_, word| word.to_lowercase())),
+            Transform::KebabCase => map_lines(text, |line| join_words(line, "-", |_, word| word.to_lowercase())),
+            Transform::Trim => map_lines(text, |line| line.trim().to_string()),
+            Transform::SortLines => edit_lines(text, |lines| lines.sort()),
+            Transform::DedupeLines => edit_lines(text, |lines| {
+                let mut seen = std::collections::HashSet::new();
+                lines.retain(|line| seen.insert(*line));
+            }),
+            Transform::ReverseLines => edit_lines(text, |lines| lines.reverse()),
+            Transform::ReverseText => map_lines(text, |line| line.chars().rev().collect()),
+            Transform::CollapseWhitespace => text.split_whitespace().collect::<Vec<_>>().join(" "),
+            Transform::Base64Encode => encoding::base64_encode(text),
+            Transform::Base64Decode => encoding::base64_decode(text)?,
+            Transform::UrlEncode => encoding::url_encode(text),
+            Transform::UrlDecode => encoding::url_decode(text)?,
+            Transform::HtmlEncode => encoding::html_encode(text),
+            Transform::HtmlDecode => encoding::html_decode(text)?,
+            Transform::JsonEscape => encoding::json_escape(text),
+            Transform::JsonUnescape => encoding::json_unescape(text)?,
+            Transform::HexEncode => encoding::hex_encode(text),
+            Transform::HexDecode => encoding::hex_decode(text)?,
+            Transform::UnicodeEscape => encoding::unicode_escape(text),
+            Transform::UnicodeUnescape => encoding::unicode_unescape(text),
+        };
+        Ok(text)
+    }
+}
+
+/// 用选中文本的转换结果替换选中的文本，多个转换时每次触发依次使用下一个
+#[derive(Debug)]
+pub struct TransformAction {
+    transform_list: Vec<Transform>,
+    index: usize,
+    paste: bool,
+}
+
+impl TransformAction {
+    pub fn new(transform_list: Vec<Transform>, paste: bool) -> Self {
+        Self {
+            transform_list,
+            index: 0,
+            paste,
+        }
+    }
+
+    pub(super) fn execute(&mut self) {
+        let transform = self.transform_list[self.index];
+        self.index = (self.index + 1) % self.transform_list.len();
+        replace_selection(self.paste, move |text| {
+            transform.apply(&text).map_err(|err| format!("Transform '{}': {}", transform.name(), err))
+        });
+    }
+}
+
+/// 选中文本的换行符，保持原有的`\r\n`或`\n`
+fn line_ending(text: &str) -> &'static str {
+    if text.contains("\r\n") { "\r\n" } else { "\n" }
+}
+
+/// 逐行转换，保留换行符与末尾的换行
+fn map_lines<F: Fn(&str) -> String>(text: &str, f: F) -> String {
+    edit_lines_owned(text, |lines| lines.iter().map(|line| f(line)).collect())
+}
+
+/// 调整行的顺序或删减行，保留换行符与末尾的换行
+fn edit_lines<F: FnOnce(&mut Vec<&str>)>(text: &str, f: F) -> String {
+    edit_lines_owned(text, |mut lines| {
+        f(&mut lines);
+        lines.into_iter().map(str::to_string).collect()
+    })
+}
+
+fn edit_lines_owned<F: FnOnce(Vec<&str>) -> Vec<String>>(text: &str, f: F) -> String {
+    let ending = line_ending(text);
+    let trailing = if text.ends_with('\n') { ending } else { "" };
+    f(text.lines().collect()).join(ending) + trailing
+}
+
+/// 把一行拆分为单词，空白与标点为分隔符，大小写变化处也拆开，例如`HTTPServer`为`HTTP`与`Server`
+fn split_words(line: &str) -> Vec<String> {
+    let mut word_list = vec![];
+    let mut word = String::new();
+    let chars = line.chars().collect::<Vec<_>>();
+
+    for (index, c) in chars.iter().copied().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                word_list.push(std::mem::take(&mut word));
+            }
+            continue
+        }
+
+        if let Some(prev) = word.chars().last() {
+            let next_is_lower = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+            let boundary = c.is_uppercase() && (prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_is_lower));
+            if boundary {
+                word_list.push(std::mem::take(&mut word));
+            }
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        word_list.push(word);
+    }
+    word_list
+}
+
+/// 拆分单词后逐个转换再连接，保留行首的缩进
+fn join_words<F: Fn(usize, &str) -> String>(line: &str, separator: &str, f: F) -> String {
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let word_list = split_words(line).iter()
+        .enumerate()
+        .map(|(index, word)| f(index, word))
+        .collect::<Vec<_>>();
+    format!("{indent}{}", word_list.join(separator))
+}
+
+/// 首字母大写，其余小写
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+        None => String::new(),
+    }
+}
+
+/// 每个单词首字母大写，保留原有的空白与标点
+fn title_case(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut word_start = true;
+    for c in text.chars() {
+        if word_start {
+            result.extend(c.to_uppercase());
+        } else {
+            result.extend(c.to_lowercase());
+        }
+        word_start = c.is_whitespace();
+    }
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(name: &str, text: &str) -> String {
+        Transform::from_name(name).unwrap().apply(text).unwrap()
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for name in transform_names() {
+            assert_eq!(Transform::from_name(name).unwrap().name(), name);
+        }
+        assert_eq!(Transform::from_name("UpperCase"), None);
+    }
+
+    #[test]
+    fn split_words_at_case_changes() {
+        assert_eq!(split_words("HTTPServer error"), ["HTTP", "Server", "error"]);
+        assert_eq!(split_words("parseJSON2Yaml"), ["parse", "JSON2", "Yaml"]);
+        assert_eq!(split_words("snake_case-and kebab"), ["snake", "case", "and", "kebab"]);
+        assert!(split_words(" -_ ").is_empty());
+    }
+
+    #[test]
+    fn word_cases() {
+        assert_eq!(apply("camel_case", "  hello_world\nHTTP server"), "  helloWorld\nhttpServer");
+        assert_eq!(apply("pascal_case", "hello world"), "HelloWorld");
+        assert_eq!(apply("snake_case", "helloWorld"), "hello_world");
+        assert_eq!(apply("kebab_case", "HelloWorld\r\n"), "hello-world\r\n");
+        assert_eq!(apply("title_case", "hello wORLD\tagain"), "Hello World\tAgain");
+        assert_eq!(apply("upper_case", "straße"), "STRASSE");
+    }
+
+    #[test]
+    fn line_edits_keep_line_endings() {
+        assert_eq!(apply("sort_lines", "b\r\na\r\nc\r\n"), "a\r\nb\r\nc\r\n");
+        assert_eq!(apply("dedupe_lines", "a\nb\na\n"), "a\nb\n");
+        assert_eq!(apply("reverse_lines", "a\nb"), "b\na");
+        assert_eq!(apply("trim", "  a \n\tb\t\n"), "a\nb\n");
+        assert_eq!(apply("reverse_text", "abc\n中文"), "cba\n文中");
+    }
+
+    #[test]
+    fn collapse_whitespace() {
+        assert_eq!(apply("collapse_whitespace", "  a \n\t b  "), "a b");
+    }
+
+    #[test]
+    fn decode_errors_keep_the_selection() {
+        assert!(Transform::Base64Decode.apply("!").is_err());
+        assert_eq!(apply("unicode_unescape", "C:\\users"), "C:\\users");
+    }
+}
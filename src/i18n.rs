@@ -68,6 +68,14 @@ impl I18nText {
     pub fn reload(&self) -> &String {
         self.get("reload")
     }
+
+    pub fn config_loaded(&self) -> &String {
+        self.get("config_loaded")
+    }
+
+    pub fn config_dir(&self) -> &String {
+        self.get("config_dir")
+    }
     
 }
 
@@ -105,6 +113,9 @@ pub enum Language {
 // 阿拉伯语（阿联酋）：ar-AE
 // 印地语（印度）：hi-IN
 
+/// 配置中可用的语言名，与`text_as_language`保持一致，此外`en`开头的都视为英语
+pub static LANGUAGE_NAMES: &[&str] = &["auto", "zh", "zh-cn", "zh-tw", "zh-hant", "ja", "ja-jp", "en"];
+
 pub fn text_as_language<S: Into<String>>(text: S) -> Language {
     use Language::*;
     let text = text.into();
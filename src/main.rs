@@ -13,6 +13,25 @@ mod i18n;
 mod units;
 
 fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    config::init_config_dir(arg_value(&args, "--config-dir").map(std::path::PathBuf::from));
+
+    // `--export-schema [文件]`：导出配置文件的JSON Schema后退出，默认写入配置文件夹
+    if has_arg(&args, "--export-schema") {
+        let path = arg_value(&args, "--export-schema")
+            .filter(|value| !value.starts_with("--"))
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|| config::config_dir().join(config::schema::SCHEMA_FILE_NAME));
+        match config::schema::export(&path) {
+            Ok(_) => println!("{}", path.display()),
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                std::process::exit(1);
+            },
+        }
+        return;
+    }
+
     fn init() {
         config::init();
     }
@@ -27,3 +46,22 @@ fn main() {
     tray.run();
 
 }
+
+/// 读取命令行参数的值，支持`--name value`与`--name=value`
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if arg == name {
+            return iter.next().map(|value| value.as_str());
+        }
+        if let Some(value) = arg.strip_prefix(name).and_then(|value| value.strip_prefix('=')) {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// 命令行参数中是否有`--name`或`--name=value`
+fn has_arg(args: &[String], name: &str) -> bool {
+    args.iter().skip(1).any(|arg| arg == name || arg.strip_prefix(name).is_some_and(|value| value.starts_with('=')))
+}
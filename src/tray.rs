@@ -1,8 +1,8 @@
-use std::sync::mpsc::Receiver;
+use std::{sync::mpsc::Receiver, time::Duration};
 
 use tray_item::{IconSource, TrayItem};
 
-use crate::{feature, i18n::I18nText};
+use crate::{config::{self, ConfigDirSource}, feature, i18n::I18nText, units::file_watch};
 
 
 pub enum TrayEndEvent {
@@ -12,6 +12,8 @@ pub enum TrayEndEvent {
 
 pub struct Tray {
     tray: TrayItem,
+    status_id: u32,
+    config_dir_id: u32,
     reload_id: u32,
     quit_id: u32,
     // tx: SyncSender<Message>,
@@ -31,41 +33,78 @@ impl Tray {
         let i18n_text = I18nText::global();
         
         let (tx, rx) = std::sync::mpsc::sync_channel(1);
+
+        let status_id = tray_inner.add_label_with_id(i18n_text.config_loaded()).unwrap();
+
+        // 显示正在使用的配置文件夹，点击时打开
+        let config_dir_id = tray_inner.add_menu_item_with_id(&config_dir_text(&i18n_text), || {
+            open_config_dir();
+        }).unwrap();
         
         let reload_id = {
             let tx = tx.clone();
-            tray_inner.add_menu_item_with_id(&i18n_text.reload(), move || {
+            tray_inner.add_menu_item_with_id(i18n_text.reload(), move || {
                 let _ = tx.send(Message::Reload);
             }).unwrap()
         };
         
         let quit_id = {
             let tx = tx.clone();
-            tray_inner.add_menu_item_with_id(&i18n_text.quit(), move || {
+            tray_inner.add_menu_item_with_id(i18n_text.quit(), move || {
                 let _ = tx.send(Message::Quit);
             }).unwrap()
         };
 
+        {
+            // 功能运行时的错误与配置错误一样显示在托盘菜单里
+            let tx = tx.clone();
+            feature::set_error_reporter(move |text| {
+                let _ = tx.try_send(Message::FeatureError(text));
+            });
+        }
+
+        {
+            // 配置文件变化时走与菜单"重新加载"相同的流程，`include`引入的配置文件夹外的文件也一并监视
+            let tx = tx.clone();
+            file_watch::watch_dir(config::config_dir(), feature::loaded_config_files, Duration::from_millis(500), move || {
+                if config::auto_reload() {
+                    let _ = tx.try_send(Message::Reload);
+                }
+            });
+        }
+
         {   
             match feature::reload() {
-                Ok(_) => {
-                    let _handle = std::thread::spawn(move || {
-                        loop {
-                            println!("启动");
-                            // 大概因为监控时又使用控制输入的各种库，有时run()会停止，所以需要重启
-                            feature::run();
+                Ok(warnings) => {
+                    if !warnings.is_empty() {
+                        for warning in warnings.iter() {
+                            println!("Reload Warning: {}", warning);
                         }
-                    });
+                        let _ = tray.inner_mut().set_menu_item_label(&warning_text(&warnings), status_id);
+                        tray.set_icon(IconSource::Resource("app-config-error-icon")).unwrap();
+                    }
                 },
                 Err(err) => {
-                    println!("Reload Error: {:?}", err);
+                    println!("Reload Error: {}", err);
+                    let _ = tray.inner_mut().set_menu_item_label(&status_text(&err), status_id);
                     tray.set_icon(IconSource::Resource("app-config-error-icon")).unwrap();
                 },
             }
+
+            // 首次加载失败时也要启动，修正配置后重新加载的绑定才能生效
+            let _handle = std::thread::spawn(move || {
+                loop {
+                    println!("启动");
+                    // 大概因为监控时又使用控制输入的各种库，有时run()会停止，所以需要重启
+                    feature::run();
+                }
+            });
         }
         
         Self {
             tray,
+            status_id,
+            config_dir_id,
             reload_id,
             quit_id,
             rx,
@@ -85,6 +124,10 @@ impl Tray {
                     }
                     self.reload();
                 }
+                Ok(Message::FeatureError(text)) => {
+                    let _ = self.tray.inner_mut().set_menu_item_label(&status_text(&text), self.status_id);
+                    self.tray.set_icon(IconSource::Resource("app-config-error-icon")).unwrap();
+                }
                 Ok(Message::Quit) => {
                     break;
                 }
@@ -97,16 +140,26 @@ impl Tray {
     fn reload(&mut self) {
         let i18n_text = I18nText::global();
         let tray = self.tray.inner_mut();
-        let _ = tray.set_menu_item_label(&i18n_text.reload(), self.reload_id);
-        let _ = tray.set_menu_item_label(&i18n_text.quit(), self.quit_id);
+        let _ = tray.set_menu_item_label(&config_dir_text(&i18n_text), self.config_dir_id);
+        let _ = tray.set_menu_item_label(i18n_text.reload(), self.reload_id);
+        let _ = tray.set_menu_item_label(i18n_text.quit(), self.quit_id);
 
         match feature::reload() {
+            Ok(warnings) if !warnings.is_empty() => {
+                for warning in warnings.iter() {
+                    println!("Reload Warning: {}", warning);
+                }
+                let _ = tray.set_menu_item_label(&warning_text(&warnings), self.status_id);
+                tray.set_icon(IconSource::Resource("app-config-error-icon")).unwrap();
+            },
             Ok(_) => {
+                let _ = tray.set_menu_item_label(i18n_text.config_loaded(), self.status_id);
                 tray.set_icon(IconSource::Resource("app-icon")).unwrap();
             },
             Err(err) => {
-                println!("Reload Error: {:?}", err);
-                feature::clear();
+                println!("Reload Error: {}", err);
+                // 保留上一次成功加载的快捷键
+                let _ = tray.set_menu_item_label(&status_text(&err), self.status_id);
                 tray.set_icon(IconSource::Resource("app-config-error-icon")).unwrap();
             },
         }
@@ -116,8 +169,49 @@ impl Tray {
         self.reload_cb = Some(reload_cb);
     }
 }
+
+fn config_dir_text(i18n_text: &I18nText) -> String {
+    let source = match config::config_dir_source() {
+        ConfigDirSource::CommandLine => "--config-dir",
+        ConfigDirSource::Environment => "CAPSLOCK_FORGE_CONFIG",
+        ConfigDirSource::User => "user",
+        ConfigDirSource::Portable => "portable",
+    };
+    format!("{} ({}): {}", i18n_text.config_dir(), source, config::config_dir().display())
+}
+
+fn open_config_dir() {
+    let program = if cfg!(windows) { "explorer" } else { "xdg-open" };
+    if let Err(err) = std::process::Command::new(program).arg(config::config_dir()).spawn() {
+        println!("Open config dir error: {}", err);
+    }
+}
+
+/// 托盘菜单里显示的错误信息，过长时截断
+fn status_text<E: std::fmt::Display>(err: &E) -> String {
+    const MAX_CHARS: usize = 120;
+    
+    let text = err.to_string();
+    if text.chars().count() > MAX_CHARS {
+        let text = text.chars().take(MAX_CHARS).collect::<String>();
+        format!("{text}…")
+    } else {
+        text
+    }
+}
+
+/// 部分快捷键被跳过时，显示第一条错误与被跳过的数量
+fn warning_text<E: std::fmt::Display>(warnings: &[E]) -> String {
+    let text = status_text(&warnings[0]);
+    match warnings.len() {
+        1 => text,
+        len => format!("{text} (+{})", len - 1),
+    }
+}
  
 enum Message {
     Reload,
+    /// 功能运行时的错误
+    FeatureError(String),
     Quit,
 }
@@ -92,7 +92,108 @@ pub mod string {
         }
         return that
     }
-    
+
+    /// 编辑距离，按字符计算，相邻字符交换算作一次编辑
+    pub fn edit_distance(a: &str, b: &str) -> usize {
+        let a = a.chars().collect::<Vec<_>>();
+        let b = b.chars().collect::<Vec<_>>();
+        let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+        for (i, row) in d.iter_mut().enumerate() {
+            row[0] = i;
+        }
+        for (j, cell) in d[0].iter_mut().enumerate() {
+            *cell = j;
+        }
+
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+                d[i][j] = (d[i - 1][j - 1] + cost).min(d[i - 1][j] + 1).min(d[i][j - 1] + 1);
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+                }
+            }
+        }
+
+        d[a.len()][b.len()]
+    }
+
+    /// 从候选中找出与输入最接近的一项，用于"did you mean"提示
+    /// 
+    /// 忽略大小写；输入是候选的前缀时（如`up`之于`uparrow`）优先给出最短的一项，
+    /// 否则按编辑距离查找，差异过大时返回`None`
+    /// 
+    /// # 例子
+    /// 
+    /// ```
+    /// use units::string::did_you_mean;
+    /// assert_eq!(did_you_mean("qoute", ["quote", "slash"]), Some("quote"));
+    /// assert_eq!(did_you_mean("up", ["u", "uparrow"]), Some("uparrow"));
+    /// ```
+    pub fn did_you_mean<'a, I: IntoIterator<Item = &'a str>>(input: &str, candidates: I) -> Option<&'a str> {
+        let input = input.to_lowercase();
+        let candidate_list: Vec<(String, &str)> = candidates.into_iter()
+            .map(|candidate| (candidate.to_lowercase(), candidate))
+            .collect();
+
+        // 单个字符作前缀的候选太多，不作提示
+        if input.chars().count() >= 2 {
+            let prefixed = candidate_list.iter()
+                .filter(|(lower, _)| lower.len() > input.len() && lower.starts_with(&input))
+                .min_by_key(|(lower, _)| lower.len());
+            if let Some((_, candidate)) = prefixed {
+                return Some(candidate)
+            }
+        }
+
+        let limit = (input.chars().count() / 3).max(1);
+        candidate_list.iter()
+            .map(|(lower, candidate)| (edit_distance(&input, lower), *candidate))
+            .filter(|(distance, _)| *distance <= limit)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, candidate)| candidate)
+    }
+
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn edit_distance_counts_chars() {
+            assert_eq!(edit_distance("", ""), 0);
+            assert_eq!(edit_distance("abc", ""), 3);
+            assert_eq!(edit_distance("", "abc"), 3);
+            assert_eq!(edit_distance("kitten", "sitting"), 3);
+            assert_eq!(edit_distance("中文", "中午"), 1);
+        }
+
+        #[test]
+        fn edit_distance_transposition() {
+            assert_eq!(edit_distance("qoute", "quote"), 1);
+            assert_eq!(edit_distance("ab", "ba"), 1);
+            assert_eq!(edit_distance("abc", "ca"), 3);
+        }
+
+        #[test]
+        fn did_you_mean_closest() {
+            assert_eq!(did_you_mean("Qoute", ["Quote", "Slash"]), Some("Quote"));
+            assert_eq!(did_you_mean("Entr", ["Escape", "Enter", "End"]), Some("Enter"));
+            assert_eq!(did_you_mean("F1", ["F11", "F2"]), Some("F11"));
+            assert_eq!(did_you_mean("xyz", ["Quote", "Slash"]), None);
+            assert_eq!(did_you_mean("quote", []), None);
+        }
+
+        #[test]
+        fn did_you_mean_prefix() {
+            let key_name_list = ["u", "uparrow", "downarrow", "leftbracket", "leftarrow"];
+            assert_eq!(did_you_mean("Up", key_name_list), Some("uparrow"));
+            assert_eq!(did_you_mean("Down", key_name_list), Some("downarrow"));
+            assert_eq!(did_you_mean("Left", key_name_list), Some("leftarrow"));
+            assert_eq!(did_you_mean("U", key_name_list), Some("u"));
+        }
+    }
 }
 
 pub mod file_io {
@@ -113,3 +214,317 @@ pub mod file_io {
         fs::write(path, contents)
     }
 }
+
+pub mod file_watch {
+    use std::{fs, path::{Path, PathBuf}, time::{Duration, SystemTime}};
+
+    /// 轮询监视文件夹内的文件，以及`file_list`返回的文件夹外的文件的变化
+    /// 
+    /// 每隔`interval`检查一次文件的修改时间与大小，变化停止一个周期后才调用`on_change`，
+    /// 编辑器保存时的连续写入只会触发一次。`file_list`每次检查时调用，要监视的文件可以随时改变
+    pub fn watch_dir<P, L, F>(dir: P, file_list: L, interval: Duration, mut on_change: F)
+    where
+        P: Into<PathBuf>,
+        L: Fn() -> Vec<PathBuf> + Send + 'static,
+        F: FnMut() + Send + 'static,
+    {
+        let dir = dir.into();
+        std::thread::spawn(move || {
+            let snapshot = || {
+                let mut list = snapshot(&dir);
+                for path in file_list() {
+                    let metadata = fs::metadata(&path).ok();
+                    list.push((path, metadata.as_ref().and_then(|metadata| metadata.modified().ok()), metadata.map(|metadata| metadata.len()).unwrap_or(0)));
+                }
+                list.sort();
+                list.dedup();
+                list
+            };
+            let mut last = snapshot();
+            let mut pending = false;
+            loop {
+                std::thread::sleep(interval);
+                let current = snapshot();
+                if current != last {
+                    last = current;
+                    pending = true;
+                } else if pending {
+                    pending = false;
+                    on_change();
+                }
+            }
+        });
+    }
+
+    /// 文件夹内（含子文件夹）全部文件的路径、修改时间与大小
+    fn snapshot(dir: &Path) -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+        let mut list = vec![];
+        let mut dir_list = vec![dir.to_path_buf()];
+        while let Some(dir) = dir_list.pop() {
+            let Ok(read_dir) = fs::read_dir(&dir) else { continue };
+            for entry in read_dir.flatten() {
+                let Ok(metadata) = entry.metadata() else { continue };
+                if metadata.is_dir() {
+                    dir_list.push(entry.path());
+                } else {
+                    list.push((entry.path(), metadata.modified().ok(), metadata.len()));
+                }
+            }
+        }
+        list.sort();
+        list
+    }
+}
+
+pub mod clipboard {
+    use std::time::Duration;
+
+    use windows_sys::Win32::{Foundation::GlobalFree, System::{DataExchange::{CloseClipboard, EmptyClipboard, GetClipboardData, OpenClipboard, SetClipboardData}, Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE}, Ole::CF_UNICODETEXT}};
+
+    /// 打开剪贴板，被其他程序占用时稍后重试
+    fn open() -> bool {
+        for _ in 0..10 {
+            if unsafe { OpenClipboard(std::ptr::null_mut()) } != 0 {
+                return true
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    /// 剪贴板中的文本，没有文本时返回`None`
+    pub fn get_text() -> Option<String> {
+        if !open() {
+            return None
+        }
+
+        let text = unsafe {
+            let handle = GetClipboardData(CF_UNICODETEXT as u32);
+            let ptr = GlobalLock(handle) as *const u16;
+            if ptr.is_null() {
+                None
+            } else {
+                let len = (0..).take_while(|i| *ptr.add(*i) != 0).count();
+                let text = String::from_utf16_lossy(std::slice::from_raw_parts(ptr, len));
+                GlobalUnlock(handle);
+                Some(text)
+            }
+        };
+
+        unsafe { CloseClipboard() };
+        text
+    }
+
+    /// 把文本放入剪贴板，替换原有内容
+    pub fn set_text(text: &str) -> bool {
+        let wide = text.encode_utf16().chain(std::iter::once(0)).collect::<Vec<_>>();
+        if !open() {
+            return false
+        }
+
+        let ok = unsafe {
+            EmptyClipboard();
+            let handle = GlobalAlloc(GMEM_MOVEABLE, wide.len() * 2);
+            let ptr = GlobalLock(handle) as *mut u16;
+            if ptr.is_null() {
+                false
+            } else {
+                std::ptr::copy_nonoverlapping(wide.as_ptr(), ptr, wide.len());
+                GlobalUnlock(handle);
+                // 成功后由剪贴板持有，失败时自行释放
+                if SetClipboardData(CF_UNICODETEXT as u32, handle).is_null() {
+                    GlobalFree(handle);
+                    false
+                } else {
+                    true
+                }
+            }
+        };
+
+        unsafe { CloseClipboard() };
+        ok
+    }
+}
+
+pub mod keyboard {
+    use caps_shortcut::Key;
+    use windows_sys::Win32::UI::{Input::KeyboardAndMouse::*, WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId}};
+
+    /// 按键当前是否按下，读取物理按键状态
+    pub fn is_key_down(key: Key) -> bool {
+        match virtual_key(key) {
+            Some(vk) => (unsafe { GetAsyncKeyState(vk as i32) } as u16 & 0x8000) != 0,
+            None => false,
+        }
+    }
+
+    /// 大写锁定是否开启
+    pub fn is_caps_lock_on() -> bool {
+        (unsafe { GetKeyState(VK_CAPITAL as i32) } & 1) != 0
+    }
+
+    /// 按键的虚拟键码
+    pub fn virtual_key(key: Key) -> Option<VIRTUAL_KEY> {
+        if let Key::Unknown(vk) = key {
+            return Some(vk as VIRTUAL_KEY)
+        }
+        VIRTUAL_KEY_TABLE.iter().find(|(k, _)| *k == key).map(|(_, vk)| *vk)
+    }
+
+    /// 虚拟键码对应的按键，监听器收到的按键与此一致，没有专门名字的按键为`Key::Unknown`
+    pub fn key_from_virtual_key(vk: VIRTUAL_KEY) -> Key {
+        VIRTUAL_KEY_TABLE.iter()
+            .find(|(_, v)| *v == vk)
+            .map(|(key, _)| *key)
+            .unwrap_or(Key::Unknown(vk as u32))
+    }
+
+    /// 扫描码对应的按键，扩展键的扫描码带`0xE0`前缀，例如右Ctrl为`0xE01D`
+    pub fn key_from_scan_code(sc: u32) -> Option<Key> {
+        let vk = unsafe { MapVirtualKeyW(sc, MAPVK_VSC_TO_VK_EX) };
+        match vk {
+            0 => None,
+            vk => Some(key_from_virtual_key(vk as VIRTUAL_KEY)),
+        }
+    }
+
+    /// 输入某个字符所需的按键与修饰键
+    #[derive(Debug, Clone, Copy)]
+    pub struct CharKey {
+        pub key: Key,
+        pub shift: bool,
+        pub ctrl: bool,
+        pub alt: bool,
+    }
+
+    /// 按前台窗口当前的键盘布局，找出输入字符`c`的按键，布局中没有该字符时返回`None`
+    pub fn key_for_char(c: char) -> Option<CharKey> {
+        let mut buffer = [0u16; 2];
+        let [c] = c.encode_utf16(&mut buffer) else {
+            return None
+        };
+
+        let result = unsafe {
+            let thread_id = GetWindowThreadProcessId(GetForegroundWindow(), std::ptr::null_mut());
+            VkKeyScanExW(*c, GetKeyboardLayout(thread_id))
+        };
+        if result == -1 {
+            return None
+        }
+
+        let [vk, shift_state] = (result as u16).to_le_bytes();
+        Some(CharKey {
+            key: key_from_virtual_key(vk as VIRTUAL_KEY),
+            shift: shift_state & 1 != 0,
+            ctrl: shift_state & 2 != 0,
+            alt: shift_state & 4 != 0,
+        })
+    }
+
+    /// 有专门名字的按键与虚拟键码
+    static VIRTUAL_KEY_TABLE: &[(Key, VIRTUAL_KEY)] = &[
+        (Key::Alt, VK_LMENU),
+        (Key::AltGr, VK_RMENU),
+        (Key::Backspace, VK_BACK),
+        (Key::CapsLock, VK_CAPITAL),
+        (Key::ControlLeft, VK_LCONTROL),
+        (Key::ControlRight, VK_RCONTROL),
+        (Key::Delete, VK_DELETE),
+        (Key::DownArrow, VK_DOWN),
+        (Key::End, VK_END),
+        (Key::Escape, VK_ESCAPE),
+        (Key::F1, VK_F1),
+        (Key::F2, VK_F2),
+        (Key::F3, VK_F3),
+        (Key::F4, VK_F4),
+        (Key::F5, VK_F5),
+        (Key::F6, VK_F6),
+        (Key::F7, VK_F7),
+        (Key::F8, VK_F8),
+        (Key::F9, VK_F9),
+        (Key::F10, VK_F10),
+        (Key::F11, VK_F11),
+        (Key::F12, VK_F12),
+        (Key::Home, VK_HOME),
+        (Key::LeftArrow, VK_LEFT),
+        (Key::MetaLeft, VK_LWIN),
+        (Key::MetaRight, VK_RWIN),
+        (Key::PageDown, VK_NEXT),
+        (Key::PageUp, VK_PRIOR),
+        (Key::Return, VK_RETURN),
+        (Key::RightArrow, VK_RIGHT),
+        (Key::ShiftLeft, VK_LSHIFT),
+        (Key::ShiftRight, VK_RSHIFT),
+        (Key::Space, VK_SPACE),
+        (Key::Tab, VK_TAB),
+        (Key::UpArrow, VK_UP),
+        (Key::PrintScreen, VK_SNAPSHOT),
+        (Key::ScrollLock, VK_SCROLL),
+        (Key::Pause, VK_PAUSE),
+        (Key::NumLock, VK_NUMLOCK),
+        (Key::BackQuote, VK_OEM_3),
+        (Key::Num1, VK_1),
+        (Key::Num2, VK_2),
+        (Key::Num3, VK_3),
+        (Key::Num4, VK_4),
+        (Key::Num5, VK_5),
+        (Key::Num6, VK_6),
+        (Key::Num7, VK_7),
+        (Key::Num8, VK_8),
+        (Key::Num9, VK_9),
+        (Key::Num0, VK_0),
+        (Key::Minus, VK_OEM_MINUS),
+        (Key::Equal, VK_OEM_PLUS),
+        (Key::KeyQ, VK_Q),
+        (Key::KeyW, VK_W),
+        (Key::KeyE, VK_E),
+        (Key::KeyR, VK_R),
+        (Key::KeyT, VK_T),
+        (Key::KeyY, VK_Y),
+        (Key::KeyU, VK_U),
+        (Key::KeyI, VK_I),
+        (Key::KeyO, VK_O),
+        (Key::KeyP, VK_P),
+        (Key::LeftBracket, VK_OEM_4),
+        (Key::RightBracket, VK_OEM_6),
+        (Key::KeyA, VK_A),
+        (Key::KeyS, VK_S),
+        (Key::KeyD, VK_D),
+        (Key::KeyF, VK_F),
+        (Key::KeyG, VK_G),
+        (Key::KeyH, VK_H),
+        (Key::KeyJ, VK_J),
+        (Key::KeyK, VK_K),
+        (Key::KeyL, VK_L),
+        (Key::SemiColon, VK_OEM_1),
+        (Key::Quote, VK_OEM_7),
+        (Key::BackSlash, VK_OEM_5),
+        (Key::IntlBackslash, VK_OEM_102),
+        (Key::KeyZ, VK_Z),
+        (Key::KeyX, VK_X),
+        (Key::KeyC, VK_C),
+        (Key::KeyV, VK_V),
+        (Key::KeyB, VK_B),
+        (Key::KeyN, VK_N),
+        (Key::KeyM, VK_M),
+        (Key::Comma, VK_OEM_COMMA),
+        (Key::Dot, VK_OEM_PERIOD),
+        (Key::Slash, VK_OEM_2),
+        (Key::Insert, VK_INSERT),
+        (Key::KpMinus, VK_SUBTRACT),
+        (Key::KpPlus, VK_ADD),
+        (Key::KpMultiply, VK_MULTIPLY),
+        (Key::KpDivide, VK_DIVIDE),
+        (Key::Kp0, VK_NUMPAD0),
+        (Key::Kp1, VK_NUMPAD1),
+        (Key::Kp2, VK_NUMPAD2),
+        (Key::Kp3, VK_NUMPAD3),
+        (Key::Kp4, VK_NUMPAD4),
+        (Key::Kp5, VK_NUMPAD5),
+        (Key::Kp6, VK_NUMPAD6),
+        (Key::Kp7, VK_NUMPAD7),
+        (Key::Kp8, VK_NUMPAD8),
+        (Key::Kp9, VK_NUMPAD9),
+        (Key::KpDelete, VK_DECIMAL),
+    ];
+}